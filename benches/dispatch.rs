@@ -0,0 +1,70 @@
+//! Measures what `DynRegex` costs compared to the monomorphized path.
+//! Run with `cargo bench`.
+//!
+//! Every variant matches the same anchored pattern, so none of them
+//! gets to skip ahead with a prefilter and the only difference is how
+//! the nodes call one another.
+
+#[macro_use]
+extern crate rusty_regex;
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use rusty_regex::dynamic::{DynRegex, MatchFn};
+use rusty_regex::util::{Accept, CaptureRe, CharChoice, Choice, Literal, NoChoice, NotChoice,
+                        OrChoice, PlusMax, Question, StarMax};
+use rusty_regex::util::named_choices::alpha;
+use rusty_regex::{Capture, Matcher, Regex, RegexThen};
+
+rusty_regex! { url_re = ^ ([:alpha:]+) "://" ([^ ' ' '/']+) ("/" [^ ' ']*)? }
+
+const TEXT: &str = "https://www.example.com/some/fairly/long/path/to/a/resource.html rest";
+const ITERATIONS: u32 = 200_000;
+
+fn bench<F>(name: &str, mut f: F)
+    where F: FnMut(&str) -> Option<usize>
+{
+    assert_eq!(f(TEXT), Some(64), "{} found the wrong match", name);
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f(black_box(TEXT)));
+    }
+    let nanos = start.elapsed().as_nanos() as f64 / ITERATIONS as f64;
+    println!("{:<40} {:>8.1} ns/iter", name, nanos);
+}
+
+fn match_end(regex: &dyn DynRegex, text: &str) -> Option<usize> {
+    let mut captures = vec![];
+    regex.match_dyn(text, 0, &mut captures, &Accept)
+}
+
+fn main() {
+    bench("monomorphized (rusty_regex!)", |text| url_re(text).map(|c| c[0].to_str().len()));
+
+    let tree = CaptureRe((CaptureRe(PlusMax(Choice(alpha))),
+                          (Literal("://"),
+                           (CaptureRe(PlusMax(Choice(NotChoice(OrChoice(CharChoice(' '),
+                                                                        OrChoice(CharChoice('/'),
+                                                                                 NoChoice)))))),
+                            (Question(CaptureRe((Literal("/"),
+                                                 (StarMax(Choice(NotChoice(OrChoice(CharChoice(' '),
+                                                                                    NoChoice)))),
+                                                  Accept)))),
+                             Accept)))));
+    bench("monomorphized (same tree, by hand)", |text| {
+        let mut captures = vec![];
+        tree.match_then(text, 0, &mut captures, &Accept)
+    });
+
+    let boxed: Box<dyn DynRegex> = Box::new(tree);
+    bench("one DynRegex around the whole tree", |text| match_end(&*boxed, text));
+
+    let function: Box<dyn DynRegex> = Box::new(url_re as MatchFn);
+    bench("generated function as DynRegex", |text| match_end(&*function, text));
+
+    let runtime = Regex::new("^([[:alpha:]]+)://([^ /]+)(/[^ ]*)?").unwrap();
+    bench("DynRegex at every node (Regex::new)", |text| {
+        runtime.find(text).map(|c: Capture| c.to_str().len())
+    });
+}
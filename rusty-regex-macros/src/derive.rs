@@ -0,0 +1,149 @@
+//! `#[derive(FromCaptures)]`, which fills each field of a struct from
+//! the named group of the same name, from the captures of a match or
+//! from a `history`.
+
+use proc_macro::{Delimiter, Span, TokenStream, TokenTree};
+
+pub struct Error {
+    pub message: String,
+    pub span: Span,
+}
+
+fn error<T>(span: Span, message: &str) -> Result<T, Error> {
+    Err(Error { message: message.to_string(), span })
+}
+
+/// How a field is filled, judging from its type.
+enum Kind {
+    /// `T`: the last capture, which must exist.
+    Required,
+    /// `Option<T>`: the last capture, if any.
+    Optional,
+    /// `Vec<T>`: every capture.
+    All,
+}
+
+pub fn from_captures(input: TokenStream) -> Result<String, Error> {
+    let tokens: Vec<TokenTree> = input.into_iter().collect();
+    let mut rest = skip_attributes_and_visibility(&tokens);
+    match rest.first() {
+        Some(TokenTree::Ident(keyword)) if keyword.to_string() == "struct" => rest = &rest[1..],
+        Some(token) => return error(token.span(), "`FromCaptures` can only be derived for structs"),
+        None => return error(Span::call_site(), "expected a struct"),
+    }
+    let name = match rest.first() {
+        Some(TokenTree::Ident(name)) => name.to_string(),
+        _ => return error(Span::call_site(), "expected a struct name"),
+    };
+    let body = match rest.get(1) {
+        Some(TokenTree::Group(body)) if body.delimiter() == Delimiter::Brace => body.stream(),
+        Some(token) => {
+            return error(token.span(),
+                         "`FromCaptures` needs a struct with named fields and no generics");
+        }
+        None => return error(Span::call_site(), "expected a struct body"),
+    };
+
+    let mut fields = String::new();
+    let mut collects = false;
+    for field in split_fields(body.into_iter().collect()) {
+        let field = skip_attributes_and_visibility(&field);
+        let (field_name, ty) = match (field.first(), field.get(1)) {
+            (Some(TokenTree::Ident(name)), Some(TokenTree::Punct(colon)))
+                if colon.as_char() == ':' => (name.to_string(), &field[2..]),
+            (Some(token), _) => return error(token.span(), "expected `name: Type`"),
+            (None, _) => continue,
+        };
+        let helper = match kind(ty) {
+            Kind::Required => "parse_named",
+            Kind::Optional => "parse_named_optional",
+            Kind::All => {
+                collects = true;
+                "parse_named_all"
+            }
+        };
+        let group = field_name.trim_start_matches("r#");
+        fields.push_str(&format!("{}: ::rusty_regex::typed::{}({:?}, captures)?,\n",
+                                 field_name, helper, group));
+    }
+
+    let mut implementation = format!("impl ::rusty_regex::typed::FromHistory for {name} {{
+        fn from_history(history: &[::rusty_regex::export::Vec<::rusty_regex::Capture>])
+                        -> ::rusty_regex::export::Result<{name}, ::rusty_regex::typed::FieldError> {{
+            let captures = &history.concat()[..];
+            ::rusty_regex::export::Result::Ok({name} {{ {fields} }})
+        }}
+    }}", name = name, fields = fields);
+
+    // A match keeps only the last iteration of a repeated group, which
+    // would quietly leave out the rest of a `Vec<T>`.
+    if !collects {
+        implementation.push_str(&format!("impl ::rusty_regex::typed::FromCaptures for {name} {{
+            fn from_captures(captures: &[::rusty_regex::Capture])
+                             -> ::rusty_regex::export::Result<{name}, ::rusty_regex::typed::FieldError> {{
+                ::rusty_regex::export::Result::Ok({name} {{ {fields} }})
+            }}
+        }}", name = name, fields = fields));
+    }
+    Ok(implementation)
+}
+
+fn skip_attributes_and_visibility(tokens: &[TokenTree]) -> &[TokenTree] {
+    let mut rest = tokens;
+    loop {
+        match (rest.first(), rest.get(1)) {
+            (Some(TokenTree::Punct(hash)), Some(TokenTree::Group(_))) if hash.as_char() == '#' => {
+                rest = &rest[2..];
+            }
+            (Some(TokenTree::Ident(vis)), next) if vis.to_string() == "pub" => {
+                rest = match next {
+                    Some(TokenTree::Group(group)) if group.delimiter() == Delimiter::Parenthesis => {
+                        &rest[2..]
+                    }
+                    _ => &rest[1..],
+                };
+            }
+            _ => return rest,
+        }
+    }
+}
+
+/// Splits the body of a struct at the commas between fields, which are
+/// those outside any `<...>`.
+fn split_fields(tokens: Vec<TokenTree>) -> Vec<Vec<TokenTree>> {
+    let mut fields = vec![vec![]];
+    let mut depth = 0;
+    for token in tokens {
+        if let TokenTree::Punct(ref punct) = token {
+            match punct.as_char() {
+                '<' => depth += 1,
+                '>' if depth > 0 => depth -= 1,
+                ',' if depth == 0 => {
+                    fields.push(vec![]);
+                    continue;
+                }
+                _ => { }
+            }
+        }
+        fields.last_mut().unwrap().push(token);
+    }
+    fields
+}
+
+/// Judges from the last path segment before the first `<`, so that
+/// `std::option::Option<T>` counts as well as `Option<T>`.
+fn kind(ty: &[TokenTree]) -> Kind {
+    let mut last = None;
+    for token in ty {
+        match *token {
+            TokenTree::Ident(ref ident) => last = Some(ident.to_string()),
+            TokenTree::Punct(ref punct) if punct.as_char() == '<' => break,
+            _ => { }
+        }
+    }
+    match last.as_ref().map(|s| &s[..]) {
+        Some("Option") => Kind::Optional,
+        Some("Vec") => Kind::All,
+        _ => Kind::Required,
+    }
+}
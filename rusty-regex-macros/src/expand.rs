@@ -0,0 +1,128 @@
+//! Turns a parsed regex into the same `rusty_regex::util` combinators
+//! that `rusty_regex!` builds, written out as Rust source.
+
+use parse::{Class, ClassItem, Named, Node, Regex};
+
+const UTIL: &str = "::rusty_regex::util";
+
+/// An expression evaluating to a function `fn(&str) -> Option<Vec<Capture>>`,
+/// just like the ones `rusty_regex!` defines.
+pub fn matcher(regex: &Regex) -> String {
+    let mut body = format!("{}::CaptureRe({})", UTIL, node(&regex.node));
+    let mut cache = String::new();
+    if !regex.anchored {
+        body = format!("{}::Cached(&PREFILTER, {})", UTIL, body);
+        cache = "static PREFILTER: ::rusty_regex::prefilter::Cache =
+                ::rusty_regex::prefilter::Cache::new();".to_string();
+    }
+    format!("{{
+        fn matcher<'text>(text: &'text str)
+                          -> Option<::rusty_regex::export::Vec<::rusty_regex::Capture<'text>>> {{
+            {cache}
+            let mut captures = ::rusty_regex::export::Vec::new();
+            let regex = {body};
+            ::rusty_regex::RegexThen::match_then(&regex, text, 0, &mut captures,
+                                                 &{util}::Accept)
+                .map(|_| captures)
+        }}
+        matcher
+    }}", cache = cache, body = body, util = UTIL)
+}
+
+pub fn node(node: &Node) -> String {
+    match *node {
+        Node::Literal(c) => format!("{}::Literal({:?})", UTIL, c.to_string()),
+        Node::Any => format!("{}::Choice({}::YesChoice)", UTIL, UTIL),
+        Node::Start => format!("{}::Start", UTIL),
+        Node::End => format!("{}::End", UTIL),
+        Node::Class(ref class) => format!("{}::Choice({})", UTIL, choices(class)),
+        Node::Group { capture: true, ref inner } => format!("{}::CaptureRe({})", UTIL, self::node(inner)),
+        Node::Group { capture: false, ref inner } => self::node(inner),
+        Node::Concat(ref items) => concat(items),
+        Node::Alt(ref branches) => {
+            let mut branches = branches.iter().rev().map(self::node);
+            let last = branches.next().unwrap();
+            branches.fold(last, |rest, branch| format!("{}::Alt({}, {})", UTIL, branch, rest))
+        }
+        Node::Repeat { ref inner, min, max, lazy } => repeat(inner, min, max, lazy),
+    }
+}
+
+/// A right-nested sequence ending in `Accept`, as `rusty_regex!` builds
+/// it, with runs of literal characters merged into one `Literal`.
+fn concat(items: &[Node]) -> String {
+    let mut parts = vec![];
+    let mut literal = String::new();
+    for item in items {
+        match *item {
+            Node::Literal(c) => literal.push(c),
+            _ => {
+                if !literal.is_empty() {
+                    parts.push(format!("{}::Literal({:?})", UTIL, literal));
+                    literal.clear();
+                }
+                parts.push(node(item));
+            }
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(format!("{}::Literal({:?})", UTIL, literal));
+    }
+    parts.iter().rev().fold(format!("{}::Accept", UTIL),
+                            |rest, part| format!("({}, {})", part, rest))
+}
+
+fn repeat(inner: &Node, min: usize, max: Option<usize>, lazy: bool) -> String {
+    let inner = node(inner);
+    match (min, max, lazy) {
+        (0, None, false) => return format!("{}::StarMax({})", UTIL, inner),
+        (0, None, true) => return format!("{}::StarMin({})", UTIL, inner),
+        (1, None, false) => return format!("{}::PlusMax({})", UTIL, inner),
+        (1, None, true) => return format!("{}::PlusMin({})", UTIL, inner),
+        (0, Some(1), false) => return format!("{}::Question({})", UTIL, inner),
+        (0, Some(1), true) => return format!("{}::QuestionMin({})", UTIL, inner),
+        _ => { }
+    }
+
+    let repeat = if lazy { "RepeatMin" } else { "RepeatMax" };
+    let max = match max {
+        Some(max) => format!("::core::option::Option::Some({})", max),
+        None => "::core::option::Option::None".to_string(),
+    };
+    format!("{}::{}({}, {}, {})", UTIL, repeat, inner, min, max)
+}
+
+fn choices(class: &Class) -> String {
+    let items = class.items.iter().rev().fold(format!("{}::NoChoice", UTIL), |rest, item| {
+        format!("{}::OrChoice({}, {})", UTIL, choice(item), rest)
+    });
+    if class.negated {
+        format!("{}::NotChoice({})", UTIL, items)
+    } else {
+        items
+    }
+}
+
+fn choice(item: &ClassItem) -> String {
+    match *item {
+        ClassItem::Char(c) => format!("{}::CharChoice({:?})", UTIL, c),
+        ClassItem::Range(lo, hi) => format!("{}::RangeChoice({:?}, {:?})", UTIL, lo, hi),
+        ClassItem::Named { name, negated } => {
+            let named = named(name);
+            if negated { format!("{}::NotChoice({})", UTIL, named) } else { named }
+        }
+    }
+}
+
+fn named(name: Named) -> String {
+    let choices = format!("{}::named_choices", UTIL);
+    match name {
+        Named::Alpha => format!("{}::alpha", choices),
+        Named::Digit => format!("{}::digit", choices),
+        Named::Space => format!("{}::space", choices),
+        Named::Word => {
+            format!("{}::OrChoice({c}::alpha, {}::OrChoice({c}::digit, {}::CharChoice('_')))",
+                    UTIL, UTIL, UTIL, c = choices)
+        }
+    }
+}
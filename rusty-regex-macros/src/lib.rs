@@ -0,0 +1,281 @@
+//! A procedural front end for `rusty-regex`. `regex!("...")` accepts
+//! conventional regex syntax and expands to the same `util` combinators
+//! that `rusty_regex!` builds, so that existing patterns can be pasted in
+//! unchanged. Use it through the `rusty_regex` crate, which re-exports it.
+
+extern crate proc_macro;
+extern crate rusty_regex_syntax as parse;
+
+mod derive;
+mod expand;
+mod nfa;
+mod validate;
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream,
+                 TokenTree};
+
+/// `regex!("^([a-z]+)://([^ /]+)")` evaluates to a function
+/// `fn(&str) -> Option<Vec<Capture>>`, just like the functions that
+/// `rusty_regex!` defines.
+#[proc_macro]
+pub fn regex(input: TokenStream) -> TokenStream {
+    let (pattern, span) = match pattern_literal(input) {
+        Ok(pattern) => pattern,
+        Err((message, span)) => return compile_error(&message, span),
+    };
+    match parse::parse(&pattern) {
+        Ok(regex) => expand::matcher(&regex).parse().unwrap(),
+        Err(error) => compile_error(&error.to_string(), span),
+    }
+}
+
+/// Implements `rusty_regex::typed::FromHistory` for a struct, filling
+/// each field from the named group `(field = ...)` of the same name, and
+/// `FromCaptures` too unless a field is a `Vec<T>`.
+#[proc_macro_derive(FromCaptures)]
+pub fn derive_from_captures(input: TokenStream) -> TokenStream {
+    match derive::from_captures(input) {
+        Ok(implementation) => implementation.parse().unwrap(),
+        Err(error) => compile_error(&error.message, error.span),
+    }
+}
+
+/// Checks the tokens of a `rusty_regex!` pattern and, if they are well
+/// formed, forwards them to the macro named in the leading `[...]`
+/// group in the comma-separated form that `rusty_regex_parse_tokens!`
+/// expects.
+#[doc(hidden)]
+#[proc_macro]
+pub fn rusty_regex_validate(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+    let callback = match tokens.next() {
+        Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Bracket => {
+            group.stream()
+        }
+        _ => panic!("rusty_regex_validate! expects `[callback] tokens...`"),
+    };
+    let pattern: Vec<TokenTree> = tokens.collect();
+    let pattern = match validate::tokens(&pattern) {
+        Ok(pattern) => pattern,
+        Err(error) => return compile_error(&error.message, error.span),
+    };
+
+    let mut arguments = TokenStream::new();
+    for token in pattern {
+        arguments.extend(vec![token, TokenTree::Punct(Punct::new(',', Spacing::Alone))]);
+    }
+    let mut expansion = callback;
+    expansion.extend(vec![TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+                          TokenTree::Group(Group::new(Delimiter::Parenthesis, arguments))]);
+    expansion
+}
+
+/// Turns `[callback] pattern...`, a pattern as checked by
+/// `rusty_regex_validate!`, into `callback!(pattern...)` with every
+/// group made non-capturing.
+#[doc(hidden)]
+#[proc_macro]
+pub fn rusty_regex_erase(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+    let callback = match tokens.next() {
+        Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Bracket => {
+            group.stream()
+        }
+        _ => panic!("rusty_regex_erase! expects `[callback] tokens...`"),
+    };
+    let pattern: Vec<TokenTree> = tokens.collect();
+    let mut expansion = callback;
+    expansion.extend(vec![TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+                          TokenTree::Group(Group::new(Delimiter::Parenthesis,
+                                                      validate::erase(&pattern).into_iter()
+                                                                               .collect()))]);
+    expansion
+}
+
+/// Compiles a pattern, as checked by `rusty_regex_validate!`, into the
+/// `&[rusty_regex::nfa::Inst]` that a `const` definition runs.
+#[doc(hidden)]
+#[proc_macro]
+pub fn rusty_regex_nfa(input: TokenStream) -> TokenStream {
+    let pattern: Vec<TokenTree> = input.into_iter().collect();
+    match nfa::program(&pattern) {
+        Ok(program) => program.parse().unwrap(),
+        Err(error) => compile_error(&error.message, error.span),
+    }
+}
+
+/// Turns `[callback] [prefix...] pattern...` into
+/// `callback! { [prefix...] [(name: Type)...] pattern... }`, listing the
+/// typed groups found anywhere in the pattern.
+#[doc(hidden)]
+#[proc_macro]
+pub fn rusty_regex_fields(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+    let (callback, prefix) = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Group(callback)), Some(prefix @ TokenTree::Group(_)))
+            if callback.delimiter() == Delimiter::Bracket => (callback.stream(), prefix),
+        _ => panic!("rusty_regex_fields! expects `[callback] [prefix] tokens...`"),
+    };
+    let pattern: Vec<TokenTree> = tokens.collect();
+    let fields = validate::fields(&pattern);
+
+    let mut arguments = TokenStream::new();
+    arguments.extend(vec![prefix,
+                          TokenTree::Group(Group::new(Delimiter::Bracket,
+                                                      fields.into_iter().collect()))]);
+    arguments.extend(pattern);
+    let mut expansion = callback;
+    expansion.extend(vec![TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+                          TokenTree::Group(Group::new(Delimiter::Brace, arguments))]);
+    expansion
+}
+
+/// Splits `[callback] item; item; ...` at each top-level `;`, invoking
+/// the macro named in the leading `[...]` group once per item.
+#[doc(hidden)]
+#[proc_macro]
+pub fn rusty_regex_split(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+    let callback = match tokens.next() {
+        Some(TokenTree::Group(ref group)) if group.delimiter() == Delimiter::Bracket => {
+            group.stream()
+        }
+        _ => panic!("rusty_regex_split! expects `[callback] items...`"),
+    };
+
+    let mut expansion = TokenStream::new();
+    for item in split_items(tokens) {
+        expansion.extend(callback.clone());
+        expansion.extend(vec![TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+                              TokenTree::Group(Group::new(Delimiter::Brace, item))]);
+    }
+    expansion
+}
+
+/// Turns `[callback] [prefix...] item; item; ...` into
+/// `callback! { [prefix...] {item} {item} ... }`, so that one macro
+/// sees every item.
+#[doc(hidden)]
+#[proc_macro]
+pub fn rusty_regex_list(input: TokenStream) -> TokenStream {
+    let mut tokens = input.into_iter();
+    let (callback, prefix) = match (tokens.next(), tokens.next()) {
+        (Some(TokenTree::Group(callback)), Some(prefix @ TokenTree::Group(_)))
+            if callback.delimiter() == Delimiter::Bracket => (callback.stream(), prefix),
+        _ => panic!("rusty_regex_list! expects `[callback] [prefix] items...`"),
+    };
+
+    let mut arguments: TokenStream = Some(prefix).into_iter().collect();
+    for item in split_items(tokens) {
+        arguments.extend(Some(TokenTree::Group(Group::new(Delimiter::Brace, item))));
+    }
+    let mut expansion = callback;
+    expansion.extend(vec![TokenTree::Punct(Punct::new('!', Spacing::Alone)),
+                          TokenTree::Group(Group::new(Delimiter::Brace, arguments))]);
+    expansion
+}
+
+/// The non-empty runs of tokens between top-level `;`s.
+fn split_items<I: Iterator<Item = TokenTree>>(tokens: I) -> Vec<TokenStream> {
+    let mut items = vec![TokenStream::new()];
+    for token in tokens {
+        match token {
+            TokenTree::Punct(ref punct) if punct.as_char() == ';' => {
+                items.push(TokenStream::new());
+            }
+            token => items.last_mut().unwrap().extend(Some(token)),
+        }
+    }
+    items.into_iter().filter(|item| !item.is_empty()).collect()
+}
+
+/// Extracts the contents of the single string literal in `input`.
+fn pattern_literal(input: TokenStream) -> Result<(String, Span), (String, Span)> {
+    let mut tokens = input.into_iter();
+    let token = match (tokens.next(), tokens.next()) {
+        (Some(token), None) => token,
+        (Some(_), Some(extra)) => {
+            return Err(("expected a single string literal".to_string(), extra.span()));
+        }
+        (None, _) => return Err(("expected a string literal".to_string(), Span::call_site())),
+    };
+    match token {
+        // Literals passed through `macro_rules!` arrive wrapped in an
+        // invisible group.
+        TokenTree::Group(ref group) if group.delimiter() == Delimiter::None => {
+            pattern_literal(group.stream())
+        }
+        TokenTree::Literal(ref literal) => {
+            match unescape(&literal.to_string()) {
+                Some(pattern) => Ok((pattern, literal.span())),
+                None => Err(("expected a string literal".to_string(), literal.span())),
+            }
+        }
+        token => Err(("expected a string literal".to_string(), token.span())),
+    }
+}
+
+/// The value of a string or character literal, given its source text,
+/// or `None` if it is some other kind of literal.
+fn unescape(source: &str) -> Option<String> {
+    if let Some(raw) = source.strip_prefix('r') {
+        let hashes = raw.len() - raw.trim_start_matches('#').len();
+        return Some(raw[hashes + 1..raw.len() - hashes - 1].to_string());
+    }
+
+    let quoted = match source.chars().next() {
+        Some(quote @ '"') | Some(quote @ '\'') => {
+            source[1..].strip_suffix(quote)?
+        }
+        _ => return None,
+    };
+    let mut value = String::new();
+    let mut chars = quoted.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            value.push(c);
+            continue;
+        }
+        match chars.next()? {
+            'n' => value.push('\n'),
+            'r' => value.push('\r'),
+            't' => value.push('\t'),
+            '0' => value.push('\0'),
+            'x' => {
+                let digits: String = chars.by_ref().take(2).collect();
+                value.push(u8::from_str_radix(&digits, 16).ok()? as char);
+            }
+            'u' => {
+                let digits: String = chars.by_ref()
+                                          .skip(1)
+                                          .take_while(|&c| c != '}')
+                                          .filter(|&c| c != '_')
+                                          .collect();
+                value.push(u32::from_str_radix(&digits, 16).ok().and_then(std::char::from_u32)?);
+            }
+            '\n' => {
+                while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                    chars.next();
+                }
+            }
+            c => value.push(c),
+        }
+    }
+    Some(value)
+}
+
+/// `compile_error!("message")`, reported at `span`.
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut literal = Literal::string(message);
+    literal.set_span(span);
+    let mut arguments = Group::new(Delimiter::Parenthesis,
+                                   TokenTree::Literal(literal).into());
+    arguments.set_span(span);
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    vec![TokenTree::Ident(Ident::new("compile_error", span)),
+         TokenTree::Punct(bang),
+         TokenTree::Group(arguments)]
+        .into_iter()
+        .collect()
+}
@@ -0,0 +1,194 @@
+//! Compiles a `rusty_regex!` pattern into the instructions that
+//! `rusty_regex::nfa::is_match` runs in a `const fn`, written out as
+//! Rust source. Only what a `const fn` can decide is supported: no
+//! fragments, constant literals, `{expr}`, predicates, `[:alpha:]` or
+//! classes of your own.
+
+use proc_macro::{Delimiter, Span, TokenTree};
+
+use unescape;
+use validate::{self, Error};
+
+const NFA: &str = "::rusty_regex::nfa";
+
+/// As many instructions as `nfa::is_match` can track.
+const MAX_INSTS: usize = 128;
+
+enum Inst {
+    Char(char),
+    Any,
+    /// Whether the class is negated, and its items as source.
+    Class(bool, Vec<String>),
+    End,
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+fn error<T>(span: Span, message: &str) -> Result<T, Error> {
+    Err(Error { message: format!("{} in a `const` pattern", message), span })
+}
+
+/// An expression of type `&[nfa::Inst]` for a checked pattern.
+pub fn program(tokens: &[TokenTree]) -> Result<String, Error> {
+    let mut program = vec![];
+    sequence(tokens, &mut program)?;
+    program.push(Inst::Match);
+    if program.len() > MAX_INSTS {
+        let span = tokens.first().map_or(Span::call_site(), TokenTree::span);
+        return error(span,
+                     &format!("{} instructions are too many (at most {})",
+                              program.len(), MAX_INSTS));
+    }
+
+    let insts: Vec<String> = program.iter().map(|inst| match *inst {
+        Inst::Char(c) => format!("{}::Inst::Char({:?})", NFA, c),
+        Inst::Any => format!("{}::Inst::Any", NFA),
+        Inst::Class(negated, ref items) => {
+            format!("{}::Inst::Class({}, &[{}])", NFA, negated, items.join(", "))
+        }
+        Inst::End => format!("{}::Inst::End", NFA),
+        Inst::Split(a, b) => format!("{}::Inst::Split({}, {})", NFA, a, b),
+        Inst::Jump(to) => format!("{}::Inst::Jump({})", NFA, to),
+        Inst::Match => format!("{}::Inst::Match", NFA),
+    }).collect();
+    Ok(format!("&[{}]", insts.join(", ")))
+}
+
+/// Compiles a sequence of tokens and their quantifiers. The commas that
+/// separate the top-level tokens of a checked pattern are skipped.
+fn sequence(tokens: &[TokenTree], program: &mut Vec<Inst>) -> Result<(), Error> {
+    let tokens: Vec<&TokenTree> = tokens.iter().filter(|token| match **token {
+        TokenTree::Punct(ref punct) => punct.as_char() != ',',
+        _ => true,
+    }).collect();
+    let quantifier = |i: usize| match tokens.get(i) {
+        Some(TokenTree::Punct(punct)) if "*+?".contains(punct.as_char()) => {
+            Some(punct.as_char())
+        }
+        _ => None,
+    };
+
+    let mut i = 0;
+    while i < tokens.len() {
+        let start = program.len();
+        match quantifier(i + 1) {
+            Some('?') => {
+                program.push(Inst::Split(0, 0));
+                item(tokens[i], program)?;
+                program[start] = Inst::Split(start + 1, program.len());
+            }
+            Some('*') => {
+                program.push(Inst::Split(0, 0));
+                item(tokens[i], program)?;
+                program.push(Inst::Jump(start));
+                program[start] = Inst::Split(start + 1, program.len());
+            }
+            Some(_) => {
+                item(tokens[i], program)?;
+                program.push(Inst::Split(start, program.len() + 1));
+            }
+            None => item(tokens[i], program)?,
+        }
+        i += 1;
+        // Laziness only changes which match is found first, which does
+        // not matter here.
+        while quantifier(i).is_some() {
+            i += 1;
+        }
+    }
+    Ok(())
+}
+
+fn item(token: &TokenTree, program: &mut Vec<Inst>) -> Result<(), Error> {
+    match *token {
+        TokenTree::Group(ref group) => {
+            let inner: Vec<TokenTree> = group.stream().into_iter().collect();
+            match group.delimiter() {
+                Delimiter::Parenthesis => {
+                    if let Some(TokenTree::Ident(keyword)) = inner.first() {
+                        if keyword.to_string() == "if" {
+                            return error(keyword.span(), "predicates cannot run");
+                        }
+                    }
+                    let header = validate::group_header(group.span(), &inner)?;
+                    sequence(&inner[header..], program)
+                }
+                Delimiter::Bracket => {
+                    let (negated, items) = class(&inner)?;
+                    program.push(Inst::Class(negated, items));
+                    Ok(())
+                }
+                _ => error(group.span(), "`{...}` cannot be used"),
+            }
+        }
+        TokenTree::Punct(_) => {
+            program.push(Inst::Any);
+            Ok(())
+        }
+        TokenTree::Literal(ref literal) if literal.to_string().starts_with('b') => {
+            error(literal.span(), "byte strings cannot be used")
+        }
+        TokenTree::Literal(ref literal) => {
+            // Checked to be a string literal.
+            let text = unescape(&literal.to_string()).unwrap_or_default();
+            program.extend(text.chars().map(Inst::Char));
+            Ok(())
+        }
+        TokenTree::Ident(ref ident) if ident.to_string() == "END" => {
+            program.push(Inst::End);
+            Ok(())
+        }
+        TokenTree::Ident(ref ident) => {
+            error(ident.span(), "fragments and constant literals cannot be used")
+        }
+    }
+}
+
+/// The items of a checked `[...]` class, as source.
+fn class(tokens: &[TokenTree]) -> Result<(bool, Vec<String>), Error> {
+    let negated = match tokens.first() {
+        Some(TokenTree::Punct(punct)) => punct.as_char() == '^',
+        _ => false,
+    };
+    let mut rest = if negated { &tokens[1..] } else { tokens };
+    let mut items = vec![];
+    while let Some((first, tail)) = rest.split_first() {
+        rest = tail;
+        match *first {
+            // `:name:`
+            TokenTree::Punct(_) => {
+                let name = rest[0].to_string();
+                rest = &rest[2..];
+                match &name[..] {
+                    "digit" => items.push(format!("{}::ClassItem::Digit", NFA)),
+                    "space" => items.push(format!("{}::ClassItem::Space", NFA)),
+                    _ => {
+                        return error(first.span(),
+                                     &format!("`[:{}:]` cannot be tested", name));
+                    }
+                }
+            }
+            TokenTree::Group(ref group) => {
+                return error(group.span(), "classes of your own cannot be used");
+            }
+            TokenTree::Literal(ref lo) => {
+                let lo = validate::char_literal(lo)?;
+                let hi = match (rest.first(), rest.get(1)) {
+                    (Some(TokenTree::Punct(dash)), Some(TokenTree::Literal(hi)))
+                        if dash.as_char() == '-' => {
+                        rest = &rest[2..];
+                        validate::char_literal(hi)?
+                    }
+                    _ => lo,
+                };
+                items.push(format!("{}::ClassItem::Range({:?}, {:?})", NFA, lo, hi));
+            }
+            // A constant holding a `char`, which a `const` can read.
+            TokenTree::Ident(ref ident) => {
+                items.push(format!("{}::ClassItem::Range({}, {})", NFA, ident, ident));
+            }
+        }
+    }
+    Ok((negated, items))
+}
@@ -0,0 +1,365 @@
+//! Checks the token-tree dialect accepted by `rusty_regex!` before it
+//! is expanded, so that mistakes are reported at the offending token
+//! rather than as "no rules expected the token" or a type error deep
+//! inside `util`.
+//!
+//! Checking also rewrites each `:path::to::Class:` in a class as
+//! `(path::to::Class)`, which `rusty_regex_parse_choices!` can match as
+//! a single token.
+
+use proc_macro::{Delimiter, Group, Ident, Punct, Spacing, Span, TokenStream, TokenTree};
+
+use unescape;
+
+/// The names that `[:name:]` may refer to in `util::named_choices`.
+const NAMED_CHOICES: &[&str] = &["alpha", "digit", "space"];
+
+pub struct Error {
+    pub message: String,
+    pub span: Span,
+}
+
+fn error<T>(span: Span, message: String) -> Result<T, Error> {
+    Err(Error { message, span })
+}
+
+/// Checks a sequence of tokens, such as the body of a pattern or group.
+pub fn tokens(tokens: &[TokenTree]) -> Result<Vec<TokenTree>, Error> {
+    // Whether the previous token can take a quantifier, and whether it
+    // was itself a `*` or `+` (which may be followed by a lazy `?`).
+    let mut repeatable = false;
+    let mut greedy = false;
+    let mut checked = vec![];
+    for token in tokens {
+        match *token {
+            TokenTree::Punct(ref punct) if "*+?".contains(punct.as_char()) => {
+                let c = punct.as_char();
+                checked.push(token.clone());
+                if c == '?' && greedy {
+                    greedy = false;
+                    continue;
+                }
+                if !repeatable {
+                    return error(punct.span(), format!("quantifier `{}` has nothing to repeat", c));
+                }
+                repeatable = false;
+                greedy = c != '?';
+            }
+            TokenTree::Punct(ref punct) if punct.as_char() == '^' => {
+                return error(punct.span(),
+                             "`^` is only allowed at the start of a pattern".to_string());
+            }
+            _ => {
+                checked.push(token_item(token)?);
+                repeatable = true;
+                greedy = false;
+            }
+        }
+    }
+    Ok(checked)
+}
+
+/// Checks a single (non-quantifier) token.
+fn token_item(token: &TokenTree) -> Result<TokenTree, Error> {
+    match *token {
+        TokenTree::Group(ref group) => {
+            let inner: Vec<TokenTree> = group.stream().into_iter().collect();
+            let checked = match group.delimiter() {
+                Delimiter::Parenthesis => {
+                    let header = group_header(group.span(), &inner)?;
+                    let mut checked = inner[..header].to_vec();
+                    checked.extend(tokens(&inner[header..])?);
+                    checked
+                }
+                Delimiter::Bracket => class(group.span(), &inner)?,
+                // `{expr}`, a user value implementing `RegexThen`.
+                Delimiter::Brace if inner.is_empty() => {
+                    return error(group.span(), "expected an expression in `{}`".to_string());
+                }
+                _ => return Ok(token.clone()),
+            };
+            Ok(regroup(group, checked))
+        }
+        TokenTree::Punct(ref punct) if punct.as_char() == '.' => Ok(token.clone()),
+        TokenTree::Punct(ref punct) => {
+            error(punct.span(), format!("unexpected `{}` in pattern", punct.as_char()))
+        }
+        TokenTree::Literal(ref literal) => {
+            let source = literal.to_string();
+            if source.starts_with('\'') {
+                error(literal.span(),
+                      format!("expected a string literal; write {:?} instead of {}",
+                              unescape(&source).unwrap_or_default(), source))
+            } else if unescape(byte_string(&source).unwrap_or(&source)).is_none() {
+                error(literal.span(), format!("expected a string literal, found `{}`", source))
+            } else {
+                Ok(token.clone())
+            }
+        }
+        // `END`, a fragment, or a constant holding a literal.
+        TokenTree::Ident(_) => Ok(token.clone()),
+    }
+}
+
+/// The source of a string literal, if `source` is a byte string.
+fn byte_string(source: &str) -> Option<&str> {
+    source.strip_prefix('b').filter(|rest| !rest.starts_with('\''))
+}
+
+/// The number of tokens at the start of a `(...)` group that precede
+/// its pattern: `?:`, a predicate `if expr =>`, a name `name =`, or a
+/// typed field `name: Type =`.
+pub fn group_header(span: Span, inner: &[TokenTree]) -> Result<usize, Error> {
+    if let Some((_, ty)) = field(inner) {
+        return match inner.get(2 + ty.len()) {
+            Some(_) if !ty.is_empty() => Ok(3 + ty.len()),
+            Some(_) => error(inner[1].span(), "expected a type after `:`".to_string()),
+            None => error(span, "expected `= pattern` after the field's type".to_string()),
+        };
+    }
+    match (inner.first(), inner.get(1)) {
+        (Some(TokenTree::Punct(q)), Some(TokenTree::Punct(c)))
+            if q.as_char() == '?' && c.as_char() == ':' => Ok(2),
+        (Some(TokenTree::Ident(_)), Some(TokenTree::Punct(eq)))
+            if eq.as_char() == '=' && eq.spacing() == Spacing::Alone => Ok(2),
+        (Some(TokenTree::Ident(keyword)), _) if keyword.to_string() == "if" => {
+            let arrow = inner.windows(2).position(|pair| {
+                match (&pair[0], &pair[1]) {
+                    (TokenTree::Punct(a), TokenTree::Punct(b)) => {
+                        a.as_char() == '=' && a.spacing() == Spacing::Joint && b.as_char() == '>'
+                    }
+                    _ => false,
+                }
+            });
+            match arrow {
+                Some(1) => error(inner[1].span(), "expected a predicate after `if`".to_string()),
+                Some(arrow) => Ok(arrow + 2),
+                None => error(span, "expected `=>` after the predicate".to_string()),
+            }
+        }
+        _ => Ok(0),
+    }
+}
+
+/// The name and type of a typed group `(name: Type = ...)`, given the
+/// group's contents.
+fn field(inner: &[TokenTree]) -> Option<(&TokenTree, &[TokenTree])> {
+    match (inner.first(), inner.get(1)) {
+        (Some(name @ TokenTree::Ident(_)), Some(TokenTree::Punct(colon)))
+            if colon.as_char() == ':' && colon.spacing() == Spacing::Alone => {
+            let ty = &inner[2..];
+            let end = ty.iter()
+                        .position(|token| match *token {
+                            TokenTree::Punct(ref eq) => {
+                                eq.as_char() == '=' && eq.spacing() == Spacing::Alone
+                            }
+                            _ => false,
+                        })
+                        .unwrap_or(ty.len());
+            Some((name, &ty[..end]))
+        }
+        _ => None,
+    }
+}
+
+/// The typed groups `(name: Type = ...)` anywhere in `tokens`, in order,
+/// as `(name: FieldType, parse)` groups. A group that may take no part
+/// in the match, because it or a group around it is followed by `?` or
+/// `*`, or whose type is written `Option<T>`, has the field type
+/// `Option<T>` and is parsed with `typed::parse_optional_field`; any
+/// other with `typed::parse_field`.
+pub fn fields(tokens: &[TokenTree]) -> Vec<TokenTree> {
+    let mut found = vec![];
+    fields_in(tokens, false, &mut found);
+    found
+}
+
+fn fields_in(tokens: &[TokenTree], optional: bool, found: &mut Vec<TokenTree>) {
+    for (i, token) in tokens.iter().enumerate() {
+        let group = match *token {
+            TokenTree::Group(ref group) if group.delimiter() == Delimiter::Parenthesis => group,
+            _ => continue,
+        };
+        let optional = optional || match tokens.get(i + 1) {
+            Some(TokenTree::Punct(punct)) => punct.as_char() == '?' || punct.as_char() == '*',
+            _ => false,
+        };
+        let inner: Vec<TokenTree> = group.stream().into_iter().collect();
+        if let Some((name, ty)) = field(&inner) {
+            let written_optional = is_option(ty);
+            let mut declaration = vec![name.clone(), inner[1].clone()];
+            if optional && !written_optional {
+                let option = "::core::option::Option<".parse::<TokenStream>().unwrap();
+                declaration.extend(option);
+                declaration.extend_from_slice(ty);
+                declaration.push(TokenTree::Punct(Punct::new('>', Spacing::Alone)));
+            } else {
+                declaration.extend_from_slice(ty);
+            }
+            let parse = if optional || written_optional {
+                "parse_optional_field"
+            } else {
+                "parse_field"
+            };
+            declaration.push(TokenTree::Punct(Punct::new(',', Spacing::Alone)));
+            declaration.push(TokenTree::Ident(Ident::new(parse, name.span())));
+            found.push(TokenTree::Group(Group::new(Delimiter::Parenthesis,
+                                                   declaration.into_iter().collect())));
+        }
+        fields_in(&inner, optional, found);
+    }
+}
+
+/// Whether `ty` is written `Option<T>`, perhaps with a path.
+fn is_option(ty: &[TokenTree]) -> bool {
+    let generics = ty.iter().position(|token| match *token {
+        TokenTree::Punct(ref punct) => punct.as_char() == '<',
+        _ => false,
+    });
+    match generics {
+        Some(i) if i > 0 => ty[i - 1].to_string() == "Option",
+        _ => false,
+    }
+}
+
+/// Checked `tokens` with every group made non-capturing, for matching
+/// where only whether there is a match matters: `(...)`, `(name = ...)`
+/// and `(name: Type = ...)` become `(?: ...)`, and `(if expr => ...)`
+/// becomes `(? if expr => ...)`, which checks without capturing.
+pub fn erase(tokens: &[TokenTree]) -> Vec<TokenTree> {
+    tokens.iter().map(|token| match *token {
+        TokenTree::Group(ref group) if group.delimiter() == Delimiter::Parenthesis => {
+            let inner: Vec<TokenTree> = group.stream().into_iter().collect();
+            let header = group_header(group.span(), &inner).unwrap_or(0);
+            let question = TokenTree::Punct(Punct::new('?', Spacing::Alone));
+            let mut erased = match inner.first() {
+                Some(TokenTree::Punct(q)) if q.as_char() == '?' => inner[..header].to_vec(),
+                Some(TokenTree::Ident(keyword)) if header > 0 && keyword.to_string() == "if" => {
+                    let mut header = inner[..header].to_vec();
+                    header.insert(0, question);
+                    header
+                }
+                _ => vec![question, TokenTree::Punct(Punct::new(':', Spacing::Alone))],
+            };
+            erased.extend(erase(&inner[header..]));
+            regroup(group, erased)
+        }
+        _ => token.clone(),
+    }).collect()
+}
+
+/// `group`, with its contents replaced by `tokens`.
+fn regroup(group: &Group, tokens: Vec<TokenTree>) -> TokenTree {
+    let mut regrouped = Group::new(group.delimiter(), tokens.into_iter().collect());
+    regrouped.set_span(group.span());
+    TokenTree::Group(regrouped)
+}
+
+/// Checks the contents of a `[...]` class.
+fn class(span: Span, tokens: &[TokenTree]) -> Result<Vec<TokenTree>, Error> {
+    let mut rest = tokens;
+    let mut checked = vec![];
+    if let Some(TokenTree::Punct(punct)) = rest.first() {
+        if punct.as_char() == '^' {
+            checked.push(rest[0].clone());
+            rest = &rest[1..];
+        }
+    }
+    if rest.is_empty() {
+        return error(span, "empty character class".to_string());
+    }
+
+    while let Some((first, tail)) = rest.split_first() {
+        rest = tail;
+        match *first {
+            TokenTree::Punct(ref punct) if punct.as_char() == ':' => {
+                let (name, tail) = named_class(punct.span(), rest)?;
+                if name.len() == 1 {
+                    checked.push(first.clone());
+                    checked.extend_from_slice(&rest[..2]);
+                } else {
+                    let path = Group::new(Delimiter::Parenthesis, name.iter().cloned().collect());
+                    checked.push(TokenTree::Group(path));
+                }
+                rest = tail;
+            }
+            TokenTree::Punct(ref punct) if punct.as_char() == '^' => {
+                return error(punct.span(),
+                             "`^` is only allowed at the start of a class".to_string());
+            }
+            TokenTree::Literal(ref lo) => {
+                let is_range = match rest.first() {
+                    Some(TokenTree::Punct(dash)) => dash.as_char() == '-',
+                    _ => false,
+                };
+                if !is_range {
+                    char_literal(lo)?;
+                    checked.push(first.clone());
+                    continue;
+                }
+                let hi = match rest.get(1) {
+                    Some(TokenTree::Literal(hi)) => hi,
+                    _ => return error(rest[0].span(), "expected a character after `-`".to_string()),
+                };
+                checked.extend_from_slice(&[first.clone(), rest[0].clone(), rest[1].clone()]);
+                rest = &rest[2..];
+                let (lo_char, hi_char) = (char_literal(lo)?, char_literal(hi)?);
+                if hi_char < lo_char {
+                    return error(lo.span(),
+                                 format!("reversed range {}-{}; did you mean {}-{}?",
+                                         lo, hi, hi, lo));
+                }
+            }
+            // A constant holding a `char`.
+            TokenTree::Ident(_) => checked.push(first.clone()),
+            ref token => {
+                return error(token.span(),
+                             "expected a character, a range or `:name:` in class".to_string());
+            }
+        }
+    }
+    Ok(checked)
+}
+
+/// Checks `name:` or `path::to::Class:` following a `:` in a class,
+/// returning the name or path and what follows the closing `:`.
+fn named_class(colon: Span, tokens: &[TokenTree])
+               -> Result<(&[TokenTree], &[TokenTree]), Error> {
+    if let Some(TokenTree::Ident(_)) = tokens.first() {
+        let mut len = 1;
+        loop {
+            match (tokens.get(len), tokens.get(len + 1), tokens.get(len + 2)) {
+                (Some(TokenTree::Punct(a)), Some(TokenTree::Punct(b)), Some(TokenTree::Ident(_)))
+                    if a.as_char() == ':' && a.spacing() == Spacing::Joint
+                        && b.as_char() == ':' => {
+                    len += 3;
+                }
+                _ => break,
+            }
+        }
+        if let Some(TokenTree::Punct(end)) = tokens.get(len) {
+            if end.as_char() == ':' {
+                let name = &tokens[..len];
+                let name_str = name[0].to_string();
+                if len > 1 || NAMED_CHOICES.contains(&&name_str[..]) {
+                    return Ok((name, &tokens[len + 1..]));
+                }
+                return error(name[0].span(),
+                             format!("unknown character class `[:{}:]`; expected one of {}, \
+                                      or a path to your own `CharRange` such as \
+                                      `[:self::{}:]`",
+                                     name_str, NAMED_CHOICES.join(", "), name_str));
+            }
+        }
+    }
+    error(colon, "expected `:name:` in class".to_string())
+}
+
+pub fn char_literal(literal: &::proc_macro::Literal) -> Result<char, Error> {
+    let source = literal.to_string();
+    let value = if source.starts_with('\'') { unescape(&source) } else { None };
+    match value.as_ref().map(|value| value.chars().collect::<Vec<_>>()) {
+        Some(ref chars) if chars.len() == 1 => Ok(chars[0]),
+        _ => error(literal.span(), format!("expected a character literal, found `{}`", source)),
+    }
+}
@@ -0,0 +1,359 @@
+//! Parser for conventional regex syntax, e.g. `^([a-z]+)://[^ /]+`,
+//! shared by the `regex!` macro and the runtime `rusty_regex::Regex`.
+
+use std::error;
+use std::fmt;
+
+pub struct Regex {
+    /// The pattern began with `^` (outside any alternation), which is
+    /// then left out of `node`.
+    pub anchored: bool,
+    pub node: Node,
+}
+
+/// The largest count `{n}`, `{n,}` or `{n,m}` may give.
+pub const MAX_REPEAT: usize = 1000;
+
+#[derive(Clone, Debug)]
+pub enum Node {
+    Literal(char),
+    Any,
+    /// `^`: the start of the text.
+    Start,
+    End,
+    Class(Class),
+    Group { capture: bool, inner: Box<Node> },
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat { inner: Box<Node>, min: usize, max: Option<usize>, lazy: bool },
+}
+
+#[derive(Clone, Debug)]
+pub struct Class {
+    pub negated: bool,
+    pub items: Vec<ClassItem>,
+}
+
+#[derive(Clone, Debug)]
+pub enum ClassItem {
+    Char(char),
+    Range(char, char),
+    Named { name: Named, negated: bool },
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Named {
+    Alpha,
+    Digit,
+    Space,
+    /// `\w`: letters, digits and `_`.
+    Word,
+}
+
+#[derive(Debug)]
+pub struct Error {
+    pub message: String,
+    /// Offset, in characters, into the pattern.
+    pub offset: usize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid regex at offset {}: {}", self.offset, self.message)
+    }
+}
+
+impl error::Error for Error { }
+
+pub fn parse(pattern: &str) -> Result<Regex, Error> {
+    let mut parser = Parser { chars: pattern.chars().collect(), position: 0 };
+    let mut node = parser.alt()?;
+    if parser.position < parser.chars.len() {
+        // `alt` only stops early at a `)` with no matching `(`.
+        return Err(parser.error("unbalanced `)`"));
+    }
+    let anchored = match node {
+        Node::Concat(ref mut items) if matches!(items.first(), Some(Node::Start)) => {
+            items.remove(0);
+            true
+        }
+        _ => false,
+    };
+    Ok(Regex { anchored, node })
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).cloned()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if c.is_some() {
+            self.position += 1;
+        }
+        c
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error { message: message.to_string(), offset: self.position }
+    }
+
+    fn error_at(&self, offset: usize, message: &str) -> Error {
+        Error { message: message.to_string(), offset }
+    }
+
+    fn alt(&mut self) -> Result<Node, Error> {
+        let mut branches = vec![self.concat()?];
+        while self.eat('|') {
+            branches.push(self.concat()?);
+        }
+        if branches.len() == 1 {
+            Ok(branches.pop().unwrap())
+        } else {
+            Ok(Node::Alt(branches))
+        }
+    }
+
+    fn concat(&mut self) -> Result<Node, Error> {
+        let mut items = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            items.push(self.repeat()?);
+        }
+        Ok(Node::Concat(items))
+    }
+
+    fn repeat(&mut self) -> Result<Node, Error> {
+        let mut node = self.atom()?;
+        let mut repeated = false;
+        loop {
+            let start = self.position;
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => self.counted()?,
+                _ => return Ok(node),
+            };
+            if repeated {
+                return Err(self.error_at(start, "quantifier follows another quantifier"));
+            }
+            // Step over the quantifier (or the `}` that `counted` stopped at).
+            self.position += 1;
+            let lazy = self.eat('?');
+            node = Node::Repeat { inner: Box::new(node), min, max, lazy };
+            repeated = true;
+        }
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}`, leaving `self` on the `}`.
+    fn counted(&mut self) -> Result<(usize, Option<usize>), Error> {
+        let start = self.position;
+        self.position += 1;
+        let min = self.number();
+        let max = if self.eat(',') {
+            if self.peek() == Some('}') { None } else { self.number() }
+        } else {
+            min
+        };
+        match (min, self.peek()) {
+            (Some(min), Some('}')) => {
+                if max.is_some_and(|max| max < min) {
+                    return Err(self.error_at(start, "counted repetition has min greater than max"));
+                }
+                if max.unwrap_or(min) > MAX_REPEAT {
+                    let message = format!("counted repetition exceeds {}", MAX_REPEAT);
+                    return Err(self.error_at(start, &message));
+                }
+                Ok((min, max))
+            }
+            _ => Err(self.error_at(start, "expected `{n}`, `{n,}` or `{n,m}`")),
+        }
+    }
+
+    fn number(&mut self) -> Option<usize> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect::<String>().parse().ok()
+    }
+
+    fn atom(&mut self) -> Result<Node, Error> {
+        let start = self.position;
+        match self.next() {
+            Some('(') => {
+                let capture = !self.eat('?');
+                if !capture && !self.eat(':') {
+                    return Err(self.error("only `(?:...)` groups are supported"));
+                }
+                let inner = self.alt()?;
+                if !self.eat(')') {
+                    return Err(self.error_at(start, "unclosed group"));
+                }
+                Ok(Node::Group { capture, inner: Box::new(inner) })
+            }
+            Some('[') => self.class(start).map(Node::Class),
+            Some('.') => Ok(Node::Any),
+            Some('$') => Ok(Node::End),
+            Some('^') => Ok(Node::Start),
+            Some(c @ '*') | Some(c @ '+') | Some(c @ '?') | Some(c @ '{') => {
+                Err(self.error_at(start, &format!("quantifier `{}` has nothing to repeat", c)))
+            }
+            Some('\\') => {
+                match self.escape(start)? {
+                    Escaped::Char(c) => Ok(Node::Literal(c)),
+                    Escaped::Named(name, negated) => {
+                        let item = ClassItem::Named { name, negated };
+                        Ok(Node::Class(Class { negated: false, items: vec![item] }))
+                    }
+                    Escaped::End => Ok(Node::End),
+                }
+            }
+            Some(c) => Ok(Node::Literal(c)),
+            None => unreachable!(),
+        }
+    }
+
+    /// Parses the rest of `[...]`, whose `[` was at `start`.
+    fn class(&mut self, start: usize) -> Result<Class, Error> {
+        let negated = self.eat('^');
+        let mut items = vec![];
+        loop {
+            let item_start = self.position;
+            let c = match self.next() {
+                // A `]` right after `[` or `[^` is a literal.
+                Some(']') if !items.is_empty() => return Ok(Class { negated, items }),
+                Some('[') if self.peek() == Some(':') => {
+                    items.push(self.posix_class(item_start)?);
+                    continue;
+                }
+                Some('\\') => {
+                    match self.escape(item_start)? {
+                        Escaped::Char(c) => c,
+                        Escaped::Named(name, negated) => {
+                            items.push(ClassItem::Named { name, negated });
+                            continue;
+                        }
+                        Escaped::End => {
+                            return Err(self.error_at(item_start, "`\\z` is not allowed in a class"));
+                        }
+                    }
+                }
+                Some(c) => c,
+                None => return Err(self.error_at(start, "unclosed character class")),
+            };
+
+            let is_range = self.peek() == Some('-')
+                && self.chars.get(self.position + 1).is_some_and(|&c| c != ']');
+            if !is_range {
+                items.push(ClassItem::Char(c));
+                continue;
+            }
+            self.position += 1;
+            let end_start = self.position;
+            let end = match self.next() {
+                Some('\\') => match self.escape(end_start)? {
+                    Escaped::Char(c) => c,
+                    _ => return Err(self.error_at(end_start, "range end must be a character")),
+                },
+                Some(c) => c,
+                None => return Err(self.error_at(start, "unclosed character class")),
+            };
+            if end < c {
+                return Err(self.error_at(item_start,
+                                         &format!("reversed range `{}-{}`", c, end)));
+            }
+            items.push(ClassItem::Range(c, end));
+        }
+    }
+
+    /// Parses the rest of `[:name:]`, whose `[` was at `start`.
+    fn posix_class(&mut self, start: usize) -> Result<ClassItem, Error> {
+        self.position += 1;
+        let negated = self.eat('^');
+        let name_start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_alphabetic()) {
+            self.position += 1;
+        }
+        let name: String = self.chars[name_start..self.position].iter().collect();
+        if !(self.eat(':') && self.eat(']')) {
+            return Err(self.error_at(start, "expected `[:name:]`"));
+        }
+        let name = match &name[..] {
+            "alpha" => Named::Alpha,
+            "digit" => Named::Digit,
+            "space" => Named::Space,
+            "word" => Named::Word,
+            _ => {
+                return Err(self.error_at(start, &format!("unknown class `[:{}:]`; expected \
+                                                          one of alpha, digit, space, word",
+                                                         name)));
+            }
+        };
+        Ok(ClassItem::Named { name, negated })
+    }
+
+    /// Parses the rest of an escape, whose `\` was at `start`.
+    fn escape(&mut self, start: usize) -> Result<Escaped, Error> {
+        let c = match self.next() {
+            Some(c) => c,
+            None => return Err(self.error_at(start, "pattern ends with `\\`")),
+        };
+        Ok(match c {
+            'd' => Escaped::Named(Named::Digit, false),
+            'D' => Escaped::Named(Named::Digit, true),
+            's' => Escaped::Named(Named::Space, false),
+            'S' => Escaped::Named(Named::Space, true),
+            'w' => Escaped::Named(Named::Word, false),
+            'W' => Escaped::Named(Named::Word, true),
+            'n' => Escaped::Char('\n'),
+            'r' => Escaped::Char('\r'),
+            't' => Escaped::Char('\t'),
+            '0' => Escaped::Char('\0'),
+            'z' => Escaped::End,
+            'x' => Escaped::Char(self.hex(start)?),
+            c if !c.is_alphanumeric() => Escaped::Char(c),
+            c => return Err(self.error_at(start, &format!("unsupported escape `\\{}`", c))),
+        })
+    }
+
+    /// Parses the rest of `\xHH` or `\x{H...}`.
+    fn hex(&mut self, start: usize) -> Result<char, Error> {
+        let braced = self.eat('{');
+        let digits_start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_hexdigit())
+            && (braced || self.position - digits_start < 2) {
+            self.position += 1;
+        }
+        let digits: String = self.chars[digits_start..self.position].iter().collect();
+        let closed = !braced || self.eat('}');
+        match u32::from_str_radix(&digits, 16).ok().and_then(::std::char::from_u32) {
+            Some(c) if closed => Ok(c),
+            _ => Err(self.error_at(start, "expected `\\xHH` or `\\x{H...}`")),
+        }
+    }
+}
+
+enum Escaped {
+    Char(char),
+    Named(Named, bool),
+    End,
+}
@@ -0,0 +1,247 @@
+//! Where a match records its captures. With the `alloc` feature that is
+//! a `Vec`; without it, a `CaptureArray` of fixed capacity, so that
+//! matching needs no allocator at all.
+
+use core::fmt;
+use core::iter::FromIterator;
+use core::mem::MaybeUninit;
+use core::ops::{Deref, DerefMut, Range};
+use core::slice;
+
+use util::Accept;
+use {Capture, RegexThen};
+
+/// The captures passed along by `RegexThen::match_then`.
+#[cfg(feature = "alloc")]
+pub type Captures<'text, T = str> = ::alloc::vec::Vec<Capture<'text, T>>;
+
+/// The captures passed along by `RegexThen::match_then`.
+#[cfg(not(feature = "alloc"))]
+pub type Captures<'text, T = str> = CaptureArray<'text, T>;
+
+/// How many captures a `CaptureArray` holds by default, and so how many
+/// a match can record without `alloc`: those of the match being tried,
+/// including the groups of a repetition's previous iteration until the
+/// next one is done. It is 16, unless the `RUSTY_REGEX_MAX_CAPTURES`
+/// environment variable gives another number when the crate is built.
+pub const MAX_CAPTURES: usize = match option_env!("RUSTY_REGEX_MAX_CAPTURES") {
+    Some(capacity) => parse_capacity(capacity),
+    None => 16,
+};
+
+const fn parse_capacity(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    assert!(!bytes.is_empty(), "RUSTY_REGEX_MAX_CAPTURES must be a number");
+    let mut capacity = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        assert!(bytes[i].is_ascii_digit(), "RUSTY_REGEX_MAX_CAPTURES must be a number");
+        capacity = capacity * 10 + (bytes[i] - b'0') as usize;
+        i += 1;
+    }
+    capacity
+}
+
+/// Captures in an array holding at most `N`, with the few `Vec`
+/// methods that matching uses. A match that would record more than
+/// `MAX_CAPTURES` fails; `push`ing onto a full array panics, as
+/// `try_push` does not.
+pub struct CaptureArray<'text, T: ?Sized + 'text = str, const N: usize = MAX_CAPTURES> {
+    len: usize,
+    /// The first `len` are initialized.
+    items: [MaybeUninit<Capture<'text, T>>; N],
+}
+
+impl<'text, T: ?Sized, const N: usize> CaptureArray<'text, T, N> {
+    pub fn new() -> CaptureArray<'text, T, N> {
+        CaptureArray { len: 0, items: [MaybeUninit::uninit(); N] }
+    }
+
+    pub fn push(&mut self, capture: Capture<'text, T>) {
+        if self.try_push(capture).is_err() {
+            panic!("a `CaptureArray` cannot hold more than {} captures", N);
+        }
+    }
+
+    /// Like `push`, but gives `capture` back if the array is full.
+    pub fn try_push(&mut self, capture: Capture<'text, T>) -> Result<(), Capture<'text, T>> {
+        if self.len == N {
+            return Err(capture);
+        }
+        self.items[self.len] = MaybeUninit::new(capture);
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    /// Removes the captures in `range`, returning them.
+    pub fn drain(&mut self, range: Range<usize>) -> IntoIter<'text, T, N> {
+        let drained: CaptureArray<'text, T, N> = self[range.clone()].iter().copied().collect();
+        self.items.copy_within(range.end..self.len, range.start);
+        self.len -= range.len();
+        drained.into_iter()
+    }
+
+    /// Replaces the captures in `range` with `captures`.
+    pub fn splice<I>(&mut self, range: Range<usize>, captures: I)
+        where I: IntoIterator<Item = Capture<'text, T>>
+    {
+        let tail: CaptureArray<'text, T, N> = self.drain(range.end..self.len).collect();
+        self.truncate(range.start);
+        self.extend(captures);
+        self.extend(tail);
+    }
+}
+
+impl<'text, T: ?Sized, const N: usize> Default for CaptureArray<'text, T, N> {
+    fn default() -> Self {
+        CaptureArray::new()
+    }
+}
+
+impl<'text, T: ?Sized, const N: usize> Deref for CaptureArray<'text, T, N> {
+    type Target = [Capture<'text, T>];
+
+    fn deref(&self) -> &[Capture<'text, T>] {
+        // Safe because the first `len` items are initialized, and
+        // `MaybeUninit<Capture>` has the same layout as `Capture`.
+        unsafe { slice::from_raw_parts(self.items.as_ptr() as *const Capture<'text, T>, self.len) }
+    }
+}
+
+impl<'text, T: ?Sized, const N: usize> DerefMut for CaptureArray<'text, T, N> {
+    fn deref_mut(&mut self) -> &mut [Capture<'text, T>] {
+        let items = self.items.as_mut_ptr() as *mut Capture<'text, T>;
+        unsafe { slice::from_raw_parts_mut(items, self.len) }
+    }
+}
+
+impl<'text, T: ?Sized + fmt::Debug, const N: usize> fmt::Debug for CaptureArray<'text, T, N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+impl<'text, T: ?Sized, const N: usize> Extend<Capture<'text, T>> for CaptureArray<'text, T, N> {
+    fn extend<I: IntoIterator<Item = Capture<'text, T>>>(&mut self, captures: I) {
+        for capture in captures {
+            self.push(capture);
+        }
+    }
+}
+
+impl<'text, T: ?Sized, const N: usize> FromIterator<Capture<'text, T>>
+    for CaptureArray<'text, T, N>
+{
+    fn from_iter<I: IntoIterator<Item = Capture<'text, T>>>(captures: I) -> Self {
+        let mut array = CaptureArray::new();
+        array.extend(captures);
+        array
+    }
+}
+
+impl<'text, T: ?Sized, const N: usize> IntoIterator for CaptureArray<'text, T, N> {
+    type Item = Capture<'text, T>;
+    type IntoIter = IntoIter<'text, T, N>;
+
+    fn into_iter(self) -> IntoIter<'text, T, N> {
+        IntoIter { array: self, next: 0 }
+    }
+}
+
+/// Iterator over the captures taken out of a `CaptureArray`.
+pub struct IntoIter<'text, T: ?Sized + 'text, const N: usize = MAX_CAPTURES> {
+    array: CaptureArray<'text, T, N>,
+    next: usize,
+}
+
+impl<'text, T: ?Sized, const N: usize> Iterator for IntoIter<'text, T, N> {
+    type Item = Capture<'text, T>;
+
+    fn next(&mut self) -> Option<Capture<'text, T>> {
+        let capture = self.array.get(self.next).copied()?;
+        self.next += 1;
+        Some(capture)
+    }
+}
+
+/// Records `capture`, or returns `None` if there is no room for it, so
+/// that the match fails rather than panics.
+#[cfg(feature = "alloc")]
+pub(crate) fn record<'text, T: ?Sized>(captures: &mut Captures<'text, T>,
+                                       capture: Capture<'text, T>)
+                                       -> Option<()> {
+    captures.push(capture);
+    Some(())
+}
+
+/// Records `capture`, or returns `None` if there is no room for it, so
+/// that the match fails rather than panics.
+#[cfg(not(feature = "alloc"))]
+pub(crate) fn record<'text, T: ?Sized>(captures: &mut Captures<'text, T>,
+                                       capture: Capture<'text, T>)
+                                       -> Option<()> {
+    captures.try_push(capture).ok()
+}
+
+/// Runs `regex`, which should capture the whole match as its first
+/// group, at 0, into `captures`.
+pub fn captures_into<'text, R>(regex: &R, text: &'text str, captures: &mut CaptureBuf<'text>)
+                               -> bool
+    where R: RegexThen
+{
+    captures.captures.clear();
+    if regex.match_then(text, 0, &mut captures.captures, &Accept).is_some() {
+        true
+    } else {
+        captures.captures.clear();
+        false
+    }
+}
+
+/// The captures of a match, as filled in by `Matcher::captures_into`
+/// or a `buffered` definition, in a buffer that is kept from one match
+/// to the next. Once it has grown to hold every group of a pattern,
+/// matching into it allocates nothing. Without `alloc`, it is a
+/// `CaptureArray`, and never allocates.
+#[derive(Debug, Default)]
+pub struct CaptureBuf<'text> {
+    pub(crate) captures: Captures<'text>,
+}
+
+impl<'text> CaptureBuf<'text> {
+    pub fn new() -> CaptureBuf<'text> {
+        CaptureBuf { captures: Captures::new() }
+    }
+
+    /// A buffer with room for the captures of `groups` groups,
+    /// counting the whole match as one.
+    #[cfg(feature = "alloc")]
+    pub fn with_capacity(groups: usize) -> CaptureBuf<'text> {
+        CaptureBuf { captures: Captures::with_capacity(groups) }
+    }
+
+    /// The buffer emptied, to match against a text that lives for a
+    /// different lifetime, keeping its allocation.
+    pub fn recycle<'other>(mut self) -> CaptureBuf<'other> {
+        self.captures.clear();
+        // Collecting from an emptied `Vec` into one of a type of the
+        // same layout reuses its allocation.
+        CaptureBuf { captures: self.captures.into_iter().map(|_| unreachable!()).collect() }
+    }
+}
+
+impl<'text> Deref for CaptureBuf<'text> {
+    type Target = [Capture<'text>];
+
+    fn deref(&self) -> &[Capture<'text>] {
+        &self.captures
+    }
+}
@@ -0,0 +1,157 @@
+//! An object-safe counterpart to `RegexThen`, so that regexes whose
+//! shape is only known at runtime can be assembled from boxed `util`
+//! nodes, and so that regexes of different types can be stored
+//! together, e.g. in a `Vec<Box<dyn DynRegex>>`.
+//!
+//! Each call through `DynRegex` costs a virtual call per continuation
+//! as well as per node; `benches/dispatch.rs` measures the difference.
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use prefilter::{FirstSet, Literals};
+use util::Mode;
+use {Capture, Captures, RegexContinuation, RegexThen, Text};
+
+/// The type of the functions that `rusty_regex!` and `regex!` define
+/// for `str`. Cast a generated function to `MatchFn` to use it as a
+/// `RegexThen` (and therefore a `DynRegex`).
+pub type MatchFn = for<'text> fn(&'text str) -> Option<Vec<Capture<'text>>>;
+
+/// `RegexThen` with the continuation passed as a trait object instead
+/// of a type parameter. Every `RegexThen` implements it, and a boxed
+/// `DynRegex` is itself a `RegexThen`, so boxed nodes can be nested
+/// inside the ordinary combinators.
+pub trait DynRegex<T: ?Sized + Text = str> {
+    fn match_dyn<'text>(&self,
+                        text: &'text T,
+                        position: usize,
+                        captures: &mut Captures<'text, T>,
+                        continuation: &dyn RegexContinuation<T>)
+                        -> Option<usize>;
+
+    fn literals_dyn(&self) -> Literals;
+
+    fn first_set_dyn(&self) -> FirstSet;
+
+    fn may_capture_dyn(&self) -> bool;
+}
+
+impl<T, R> DynRegex<T> for R
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_dyn<'text>(&self,
+                        text: &'text T,
+                        position: usize,
+                        captures: &mut Captures<'text, T>,
+                        continuation: &dyn RegexContinuation<T>)
+                        -> Option<usize>
+    {
+        self.match_then(text, position, captures, &continuation)
+    }
+
+    fn literals_dyn(&self) -> Literals {
+        self.literals()
+    }
+
+    fn first_set_dyn(&self) -> FirstSet {
+        self.first_set()
+    }
+
+    fn may_capture_dyn(&self) -> bool {
+        self.may_capture()
+    }
+}
+
+impl<T, R> RegexThen<T> for Box<R>
+    where T: ?Sized + Text, R: ?Sized + DynRegex<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        (**self).match_dyn(text, position, captures, continuation)
+    }
+
+    fn literals(&self) -> Literals {
+        (**self).literals_dyn()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        (**self).first_set_dyn()
+    }
+
+    fn may_capture(&self) -> bool {
+        (**self).may_capture_dyn()
+    }
+}
+
+impl<T, R> RegexThen<T> for &R
+    where T: ?Sized + Text, R: ?Sized + RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        (**self).match_then(text, position, captures, continuation)
+    }
+
+    fn literals(&self) -> Literals {
+        (**self).literals()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        (**self).first_set()
+    }
+
+    fn may_capture(&self) -> bool {
+        (**self).may_capture()
+    }
+}
+
+impl<T, C> RegexContinuation<T> for &C
+    where T: ?Sized + Text, C: ?Sized + RegexContinuation<T>
+{
+    fn match_continue<'text>(&self,
+                             text: &'text T,
+                             position: usize,
+                             captures: &mut Captures<'text, T>)
+                             -> Option<usize>
+    {
+        (**self).match_continue(text, position, captures)
+    }
+
+    fn mode(&self) -> Mode {
+        (**self).mode()
+    }
+}
+
+/// A generated function matches as a single unit: it searches the text
+/// from `position` onward (or matches only at `position`, if its
+/// pattern begins with `^`), and its first match is passed on to the
+/// continuation. If the continuation fails, no other match is tried.
+impl RegexThen for MatchFn {
+    fn match_then<'text,C>(&self,
+                           text: &'text str,
+                           position: usize,
+                           captures: &mut Captures<'text>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation
+    {
+        let found = self(&text[position..])?;
+        let end = position + found[0].end;
+        captures.extend(found.into_iter().map(|c| {
+            Capture { text, start: position + c.start, end: position + c.end, ..c }
+        }));
+        continuation.match_continue(text, end, captures)
+    }
+}
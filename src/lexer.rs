@@ -0,0 +1,62 @@
+//! Tokenizers defined by `rusty_lexer!`: a list of `Token = pattern`
+//! rules, each matched where the previous token ended. The rule with
+//! the longest match wins, and of rules matching the same length, the
+//! first listed. Each rule's own match is the one the engine finds
+//! first, as everywhere else (so a rule `"a"+` takes every `a`).
+
+use core::error::Error;
+use core::fmt;
+use core::ops::Range;
+
+/// The input at `position` matched no rule.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct LexError {
+    pub position: usize,
+}
+
+impl fmt::Display for LexError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unrecognized input at byte {}", self.position)
+    }
+}
+
+impl Error for LexError { }
+
+/// Iterator returned by the `lex` function of a `rusty_lexer!` enum.
+/// Unrecognized input is reported as a `LexError`, after which lexing
+/// resumes at the next character.
+pub struct Tokens<'text, K> {
+    text: &'text str,
+    position: usize,
+
+    /// The token at a position and where it ends, if any rule matches
+    /// something there.
+    longest_at: fn(&str, usize) -> Option<(K, usize)>,
+}
+
+impl<'text, K> Tokens<'text, K> {
+    pub fn new(text: &'text str, longest_at: fn(&str, usize) -> Option<(K, usize)>)
+               -> Tokens<'text, K> {
+        Tokens { text, position: 0, longest_at }
+    }
+}
+
+impl<'text, K> Iterator for Tokens<'text, K> {
+    type Item = Result<(K, &'text str, Range<usize>), LexError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let start = self.position;
+        let rest = &self.text[start..];
+        let c = rest.chars().next()?;
+        match (self.longest_at)(self.text, start) {
+            Some((token, end)) => {
+                self.position = end;
+                Some(Ok((token, &self.text[start..end], start..end)))
+            }
+            None => {
+                self.position += c.len_utf8();
+                Some(Err(LexError { position: start }))
+            }
+        }
+    }
+}
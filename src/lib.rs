@@ -1,42 +1,201 @@
+//! Without the default `std` feature the crate is `no_std`: the
+//! engine needs only `core`, recording captures in a fixed-capacity
+//! `captures::CaptureArray`. The `alloc` feature records them in a
+//! `Vec` instead, and brings back everything that returns one.
+
+#![no_std]
+
+#[cfg(feature = "alloc")]
+#[macro_use]
+extern crate alloc;
+#[cfg(feature = "std")]
+extern crate std;
+
+// Lets code generated by `regex!`, which names `::rusty_regex`, work
+// inside this crate too.
+extern crate self as rusty_regex;
+
+extern crate rusty_regex_macros;
+#[cfg(feature = "std")]
+extern crate rusty_regex_syntax;
+
 #[macro_use]
 pub mod macros;
 
+pub mod captures;
+#[cfg(feature = "alloc")]
+pub mod dynamic;
+pub mod lexer;
+#[cfg(feature = "alloc")]
+pub mod matcher;
+pub mod nfa;
+pub mod prefilter;
+pub mod reverse;
+#[cfg(feature = "std")]
+pub mod runtime;
+#[cfg(feature = "std")]
+pub mod set;
+#[cfg(feature = "std")]
+pub mod stream;
+pub mod text;
+#[cfg(feature = "alloc")]
+pub mod typed;
 pub mod util;
 
-#[cfg(test)]
+pub use rusty_regex_macros::regex;
+#[cfg(feature = "alloc")]
+pub use rusty_regex_macros::FromCaptures;
+#[doc(hidden)]
+pub use rusty_regex_macros::{rusty_regex_erase, rusty_regex_fields, rusty_regex_list,
+                             rusty_regex_nfa, rusty_regex_split, rusty_regex_validate};
+pub use captures::{CaptureBuf, Captures};
+#[cfg(feature = "alloc")]
+pub use matcher::Matcher;
+#[cfg(feature = "std")]
+pub use runtime::{ParseError, Regex};
+pub use text::{ByteText, Text, Utf8Bytes};
+#[cfg(feature = "alloc")]
+pub use typed::{FromCaptures, FromHistory};
+
+// What generated code names, which cannot count on `std` or `alloc`
+// being in scope where it is expanded.
+#[doc(hidden)]
+pub mod export {
+    pub use core::cell::Cell;
+    pub use core::result::Result;
+    #[cfg(feature = "alloc")]
+    pub use alloc::vec::Vec;
+    #[cfg(feature = "std")]
+    pub use std::io::{BufRead, Result as IoResult};
+    #[cfg(feature = "std")]
+    pub use std::sync::OnceLock;
+}
+
+#[cfg(all(test, feature = "std"))]
 mod test;
 
-pub trait RegexThen {
+pub trait RegexThen<T: ?Sized + Text = str> {
     fn match_then<'text,C>(&self,
-                           text: &'text str,
+                           text: &'text T,
                            position: usize,
-                           captures: &mut Vec<Capture<'text>>,
+                           captures: &mut Captures<'text, T>,
                            continuation: &C)
                            -> Option<usize>
-        where C: RegexContinuation;
+        where C: RegexContinuation<T>;
+
+    /// Literal text that every match must begin with or contain, used
+    /// to skip ahead during unanchored searches. The default claims
+    /// nothing, which is always correct.
+    fn literals(&self) -> prefilter::Literals {
+        prefilter::Literals::unknown()
+    }
+
+    /// The characters a match can begin with, used to skip start
+    /// positions during unanchored searches. The default is every
+    /// character.
+    fn first_set(&self) -> prefilter::FirstSet {
+        prefilter::FirstSet::any()
+    }
+
+    /// Whether a match may record any captures, so that repetitions
+    /// that cannot need not keep track of their iterations. The default
+    /// conservatively says yes.
+    fn may_capture(&self) -> bool {
+        true
+    }
 }
 
-pub trait RegexContinuation {
+pub trait RegexContinuation<T: ?Sized + Text = str> {
     fn match_continue<'text>(&self,
-                             text: &'text str,
+                             text: &'text T,
                              position: usize,
-                             captures: &mut Vec<Capture<'text>>)
+                             captures: &mut Captures<'text, T>)
                              -> Option<usize>;
+
+    /// What the match is run for, which tells repetitions how much of
+    /// their iterations to keep. A continuation that wraps another
+    /// should pass on its mode.
+    fn mode(&self) -> util::Mode {
+        util::Mode::Captures
+    }
+}
+
+/// Like `RegexThen`, but matches right-to-left: `position` is where the
+/// match ends, and the continuation receives where it starts. Captures
+/// are not recorded in this direction.
+pub trait RegexRev<T: ?Sized + Text = str> {
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>;
+}
+
+pub trait RegexRevContinuation<T: ?Sized + Text = str> {
+    fn match_rev_continue(&self,
+                          text: &T,
+                          position: usize)
+                          -> Option<usize>;
 }
 
 pub trait CharRange {
     fn test(&self, c: char) -> bool;
+
+    /// Whether `test` may accept some non-ASCII character. The default
+    /// conservatively says yes.
+    fn may_match_non_ascii(&self) -> bool {
+        true
+    }
 }
 
-#[derive(Copy, Clone, Debug, Eq, PartialEq)]
-pub struct Capture<'text> {
-    text: &'text str,
+#[derive(Debug)]
+pub struct Capture<'text, T: ?Sized + 'text = str> {
+    text: &'text T,
     start: usize,
     end: usize,
+    group: Option<&'static util::Group>,
+}
+
+impl<'text, T: ?Sized> Copy for Capture<'text, T> { }
+
+/// Two captures are equal if they are the same span of equal text, with
+/// the same name; which group they come from is not compared.
+impl<'text, T: ?Sized + PartialEq> PartialEq for Capture<'text, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text && self.start == other.start && self.end == other.end
+            && self.group.and_then(|g| g.name) == other.group.and_then(|g| g.name)
+    }
+}
+
+impl<'text, T: ?Sized + Eq> Eq for Capture<'text, T> { }
+
+impl<'text, T: ?Sized> Clone for Capture<'text, T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<'text, T: ?Sized> Capture<'text, T> {
+    /// A capture of `text[start..end]` by an unnamed group.
+    pub fn new(text: &'text T, start: usize, end: usize) -> Capture<'text, T> {
+        Capture { text, start, end, group: None }
+    }
+}
+
+impl<'text, T: ?Sized + Text> Capture<'text, T> {
+    pub fn as_bytes(self) -> &'text [u8] {
+        &self.text.as_bytes()[self.start..self.end]
+    }
+
+    /// The name of the group, for a named group `(name = ...)`.
+    pub fn name(self) -> Option<&'static str> {
+        self.group.and_then(|group| group.name)
+    }
 }
 
 impl<'text> Capture<'text> {
-    fn to_str(self) -> &'text str {
+    pub fn to_str(self) -> &'text str {
         &self.text[self.start..self.end]
     }
 }
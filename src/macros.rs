@@ -2,28 +2,689 @@
 // other macros should be marked `#[doc(hidden)]` and considered
 // internal implementation details.
 
+/// Defines functions (or, with `struct`, types implementing `Matcher`)
+/// matching patterns written in this crate's token dialect; see the
+/// test file for examples. Each definition may be preceded by
+/// attributes (including `///` docs) and a visibility, which is `pub`
+/// if none is given (`pub(self)` for a private one), and several
+/// definitions may be separated by `;`:
+///
+#[cfg_attr(feature = "alloc", doc = "```")]
+#[cfg_attr(not(feature = "alloc"), doc = "```ignore")]
+/// #[macro_use] extern crate rusty_regex;
+/// rusty_regex! {
+///     /// A run of digits.
+///     number_re = ([:digit:]+);
+///     #[inline]
+///     pub(crate) word_re = ^ ([:alpha:]+);
+///     pub(self) struct Spaces = [:space:]+
+/// }
+/// # fn main() {
+/// # assert!(number_re("abc 123").is_some());
+/// # assert!(word_re("abc 123").is_some());
+/// # }
+/// ```
+///
+/// Malformed patterns are reported at the offending token:
+///
+#[cfg_attr(feature = "alloc", doc = "```compile_fail")]
+#[cfg_attr(not(feature = "alloc"), doc = "```ignore")]
+/// #[macro_use] extern crate rusty_regex;
+/// rusty_regex! { reversed_re = ['z'-'a'] } // reversed range 'z'-'a'
+/// # fn main() { }
+/// ```
+///
+#[cfg_attr(feature = "alloc", doc = "```compile_fail")]
+#[cfg_attr(not(feature = "alloc"), doc = "```ignore")]
+/// #[macro_use] extern crate rusty_regex;
+/// rusty_regex! { unknown_re = [:letter:]+ } // unknown character class
+/// # fn main() { }
+/// ```
+///
+#[cfg_attr(feature = "alloc", doc = "```compile_fail")]
+#[cfg_attr(not(feature = "alloc"), doc = "```ignore")]
+/// #[macro_use] extern crate rusty_regex;
+/// rusty_regex! { dangling_re = (* "b") } // nothing to repeat
+/// # fn main() { }
+/// ```
+///
+#[cfg_attr(feature = "alloc", doc = "```compile_fail")]
+#[cfg_attr(not(feature = "alloc"), doc = "```ignore")]
+/// #[macro_use] extern crate rusty_regex;
+/// rusty_regex! { magic_re = b"\x7fELF" } // byte strings only match `bytes`
+/// # fn main() { }
+/// ```
+///
+/// ```compile_fail
+/// #[macro_use] extern crate rusty_regex;
+/// rusty_regex! { const word_re = [:alpha:]+ } // not available in a `const fn`
+/// # fn main() { }
+/// ```
 #[macro_export]
 macro_rules! rusty_regex {
-    ($name:ident = ^ $($tokens:tt)+) => {
-        pub fn $name<'text>(text: &'text str) -> Option<Vec<$crate::Capture<'text>>> {
-            let mut captures = vec![];
-            let regex = $crate::util::CaptureRe(rusty_regex_parse_tokens!($($tokens,)*));
-            $crate::RegexThen::match_then(&regex, text, 0, &mut captures, &$crate::util::Accept)
-                .map(|_| captures)
+    ($($items:tt)+) => {
+        $crate::rusty_regex_split! { [$crate::rusty_regex_public] $($items)+ }
+    };
+}
+
+// A definition without a visibility is `pub`, as definitions always
+// were before they could be given one; `pub(self)` makes it private.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_public {
+    ($(#[$($attr:tt)*])* pub(self) $($item:tt)+) => {
+        $crate::rusty_regex_item! { $(#[$($attr)*])* $($item)+ }
+    };
+
+    ($(#[$($attr:tt)*])* pub $($item:tt)+) => {
+        $crate::rusty_regex_item! { $(#[$($attr)*])* pub $($item)+ }
+    };
+
+    ($(#[$($attr:tt)*])* $kind:ident $($item:tt)*) => {
+        $crate::rusty_regex_item! { $(#[$($attr)*])* pub $kind $($item)* }
+    };
+}
+
+// A single definition from `rusty_regex!`, with its attributes and
+// visibility.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_item {
+    // `bytes utf8` matches `&[u8]`, decoding UTF-8 where it is valid
+    ($(#[$($attr:tt)*])* $vis:vis bytes utf8 $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<'text>(text: &'text [u8])
+            -> Option<$crate::export::Vec<$crate::Capture<'text, $crate::Utf8Bytes>>> {
+            rusty_regex_match!($crate::Utf8Bytes::new(text); $($tokens)+)
+        }
+    };
+
+    // `bytes` matches `&[u8]`, one byte per character
+    ($(#[$($attr:tt)*])* $vis:vis bytes $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<'text>(text: &'text [u8])
+                             -> Option<$crate::export::Vec<$crate::Capture<'text, [u8]>>> {
+            rusty_regex_match!(text; $($tokens)+)
+        }
+    };
+
+    // `stream utf8` decodes the stream as UTF-8 rather than Latin-1
+    ($(#[$($attr:tt)*])* $vis:vis stream utf8 $name:ident = ^ $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<R: $crate::export::BufRead>(reader: R)
+            -> impl Iterator<Item = $crate::export::IoResult<
+                   $crate::export::Vec<$crate::stream::StreamCapture>>>
+        {
+            let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+            $crate::stream::Matches::<_, _, $crate::stream::Utf8>::anchored(reader, regex)
+        }
+    };
+
+    ($(#[$($attr:tt)*])* $vis:vis stream utf8 $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<R: $crate::export::BufRead>(reader: R)
+            -> impl Iterator<Item = $crate::export::IoResult<
+                   $crate::export::Vec<$crate::stream::StreamCapture>>>
+        {
+            let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+            $crate::stream::Matches::<_, _, $crate::stream::Utf8>::new(reader, regex)
         }
     };
 
-    // if no leading `^` is provided, insert an implicit `.*?`
-    ($name:ident = $($tokens:tt)+) => {
-        pub fn $name<'text>(text: &'text str) -> Option<Vec<$crate::Capture<'text>>> {
-            let mut captures = vec![];
-            let regex =
-                ($crate::util::StarMin($crate::util::Choice($crate::util::YesChoice)),
-                 $crate::util::CaptureRe(rusty_regex_parse_tokens!($($tokens,)*)));
-            $crate::RegexThen::match_then(&regex, text, 0, &mut captures, &$crate::util::Accept)
+    // `stream` searches a `BufRead` without reading it all into memory,
+    // one byte per character
+    ($(#[$($attr:tt)*])* $vis:vis stream $name:ident = ^ $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<R: $crate::export::BufRead>(reader: R)
+            -> impl Iterator<Item = $crate::export::IoResult<
+                   $crate::export::Vec<$crate::stream::StreamCapture>>>
+        {
+            let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+            $crate::stream::Matches::<_, _, $crate::stream::Latin1>::anchored(reader, regex)
+        }
+    };
+
+    ($(#[$($attr:tt)*])* $vis:vis stream $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<R: $crate::export::BufRead>(reader: R)
+            -> impl Iterator<Item = $crate::export::IoResult<
+                   $crate::export::Vec<$crate::stream::StreamCapture>>>
+        {
+            let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+            $crate::stream::Matches::<_, _, $crate::stream::Latin1>::new(reader, regex)
+        }
+    };
+
+    // `suffix` finds the leftmost match that extends to the end of the
+    // text, locating its start with a right-to-left pass
+    ($(#[$($attr:tt)*])* $vis:vis suffix $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<'text>(text: &'text str)
+                             -> Option<$crate::export::Vec<$crate::Capture<'text>>> {
+            let mut captures = $crate::export::Vec::new();
+            let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+            let start = $crate::reverse::ends_with(&regex, text)?;
+            $crate::RegexThen::match_then(&(regex, $crate::util::End), text, start,
+                                          &mut captures, &$crate::util::Accept)
                 .map(|_| captures)
         }
     };
+
+    // `struct` defines a zero-sized type implementing `Matcher`, and
+    // `RegexThen` so that it can be used inside other patterns
+    ($(#[$($attr:tt)*])* $vis:vis struct $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        #[derive(Copy, Clone, Debug, Default)]
+        $vis struct $name;
+
+        rusty_regex_struct!([] $(#[$($attr)*])* ; struct $name; $($tokens)+);
+    };
+
+    // `fragment` defines a piece of pattern that other patterns can
+    // splice in by name; unlike `struct`, it captures nothing itself
+    ($(#[$($attr:tt)*])* $vis:vis fragment $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        #[allow(non_camel_case_types)]
+        #[derive(Copy, Clone, Debug, Default)]
+        $vis struct $name;
+
+        rusty_regex_struct!([] $(#[$($attr)*])* ; fragment $name; $($tokens)+);
+    };
+
+    // `name -> Struct` parses each typed group `(field: Type = ...)`
+    // into a field of `Struct`
+    ($(#[$($attr:tt)*])* $vis:vis $name:ident -> $record:ident = $($tokens:tt)+) => {
+        $crate::rusty_regex_fields! {
+            [$crate::rusty_regex_typed] [$(#[$($attr)*])* $vis $name $record] $($tokens)+
+        }
+    };
+
+    // `history` keeps every iteration of a repeated group: one `Vec`
+    // per group, in the order the groups would otherwise be reported
+    ($(#[$($attr:tt)*])* $vis:vis history $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<'text>(text: &'text str)
+            -> Option<$crate::export::Vec<$crate::export::Vec<$crate::Capture<'text>>>> {
+            rusty_regex_match!(history text; $($tokens)+)
+        }
+    };
+
+    // `buffered` fills a caller's `CaptureBuf`, reusing its allocation,
+    // and returns whether there was a match
+    ($(#[$($attr:tt)*])* $vis:vis buffered $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<'text>(text: &'text str, captures: &mut $crate::CaptureBuf<'text>) -> bool {
+            rusty_regex_match!(buffered text, captures; $($tokens)+)
+        }
+    };
+
+    // `is_match` only says whether there is a match: its groups are
+    // not captured, and nothing is allocated
+    ($(#[$($attr:tt)*])* $vis:vis is_match $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name(text: &str) -> bool {
+            rusty_regex_match!(is_match text; $($tokens)+)
+        }
+    };
+
+    // `const` defines a `const fn` saying whether there is a match, for
+    // patterns without captures that `nfa::is_match` can run
+    ($(#[$($attr:tt)*])* $vis:vis const $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis const fn $name(text: &str) -> bool {
+            rusty_regex_match!(const text; $($tokens)+)
+        }
+    };
+
+    // `posix` finds the leftmost-longest match, with POSIX rules for
+    // the captures, instead of the first match found
+    ($(#[$($attr:tt)*])* $vis:vis posix $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<'text>(text: &'text str)
+                             -> Option<$crate::export::Vec<$crate::Capture<'text>>> {
+            rusty_regex_match!(posix text; $($tokens)+)
+        }
+    };
+
+    ($(#[$($attr:tt)*])* $vis:vis $name:ident = $($tokens:tt)+) => {
+        $(#[$($attr)*])*
+        $vis fn $name<'text>(text: &'text str)
+                             -> Option<$crate::export::Vec<$crate::Capture<'text>>> {
+            rusty_regex_match!(text; $($tokens)+)
+        }
+    };
+}
+
+/// Defines functions testing many patterns against a text at once,
+/// returning the indices of those that match, in order. The patterns
+/// are written as in `rusty_regex!`, separated by `;`:
+///
+#[cfg_attr(feature = "std", doc = "```")]
+#[cfg_attr(not(feature = "std"), doc = "```ignore")]
+/// #[macro_use] extern crate rusty_regex;
+/// rusty_regex_set! {
+///     pub log_kind = {
+///         ^ "ERROR";
+///         "timeout";
+///         "disk" [:space:]+ "full"
+///     }
+/// }
+/// # fn main() {
+/// assert_eq!(log_kind("ERROR: disk  full"), vec![0, 2]);
+/// assert_eq!(log_kind("all good"), Vec::<usize>::new());
+/// # }
+/// ```
+///
+/// The patterns share a single pass over the text that rules out those
+/// whose literals or first characters do not occur in it; see `set`.
+#[macro_export]
+macro_rules! rusty_regex_set {
+    ($($items:tt)+) => {
+        $crate::rusty_regex_split! { [$crate::rusty_regex_set_item] $($items)+ }
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_set_item {
+    ($(#[$($attr:tt)*])* $vis:vis $name:ident = { $($patterns:tt)+ }) => {
+        $crate::rusty_regex_list! {
+            [$crate::rusty_regex_set_fn] [$(#[$($attr)*])* $vis $name] $($patterns)+
+        }
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_set_fn {
+    ([$(#[$($attr:tt)*])* $vis:vis $name:ident] $({$($pattern:tt)+})+) => {
+        $(#[$($attr)*])*
+        $vis fn $name(text: &str) -> $crate::export::Vec<usize> {
+            static SET: $crate::export::OnceLock<$crate::set::RegexSet> =
+                $crate::export::OnceLock::new();
+            SET.get_or_init(|| {
+                $crate::set::RegexSet::new($crate::export::Vec::from([
+                    $(rusty_regex_set_pattern!($($pattern)+)),+
+                ]))
+            }).matches(text)
+        }
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_set_pattern {
+    (^ $($tokens:tt)+) => {
+        $crate::set::Pattern::new(true,
+                                  &rusty_regex_erased_pattern!($($tokens)+),
+                                  |text| rusty_regex_match!(is_match text; ^ $($tokens)+))
+    };
+
+    ($($tokens:tt)+) => {
+        $crate::set::Pattern::new(false,
+                                  &rusty_regex_erased_pattern!($($tokens)+),
+                                  |text| rusty_regex_match!(is_match text; $($tokens)+))
+    };
+}
+
+/// Defines a token enum with one variant per rule, and a `lex`
+/// function splitting a text into `(Token, &str, Range<usize>)`s. At
+/// each position the rule with the longest match wins, the first
+/// listed on a tie; see `lexer`.
+///
+/// ```
+/// #[macro_use] extern crate rusty_regex;
+/// rusty_lexer! {
+///     pub enum Token {
+///         Ident = [:alpha: '_'] [:alpha: :digit: '_']*;
+///         Number = [:digit:]+;
+///         Arrow = "->";
+///         Minus = "-";
+///         Space = [:space:]+
+///     }
+/// }
+/// # fn main() {
+/// let tokens: Vec<_> = Token::lex("x -> 42").map(|t| t.unwrap().0).collect();
+/// assert_eq!(tokens, [Token::Ident, Token::Space, Token::Arrow, Token::Space, Token::Number]);
+/// # }
+/// ```
+#[macro_export]
+macro_rules! rusty_lexer {
+    ($(#[$($attr:tt)*])* $vis:vis enum $name:ident { $($rules:tt)+ }) => {
+        $crate::rusty_regex_list! {
+            [$crate::rusty_lexer_enum] [$(#[$($attr)*])* $vis $name] $($rules)+
+        }
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_lexer_enum {
+    ([$(#[$($attr:tt)*])* $vis:vis $name:ident]
+     $({$(#[$($rule_attr:tt)*])* $token:ident = $($pattern:tt)+})+) => {
+        $(#[$($attr)*])*
+        #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq)]
+        $vis enum $name {
+            $($(#[$($rule_attr)*])* $token,)+
+        }
+
+        impl $name {
+            /// The tokens of `text`, in order.
+            $vis fn lex<'text>(text: &'text str) -> $crate::lexer::Tokens<'text, $name> {
+                $crate::lexer::Tokens::new(text, $name::longest_at)
+            }
+
+            fn longest_at(text: &str, position: usize) -> Option<($name, usize)> {
+                let mut longest = None;
+                $(
+                    let end = $crate::RegexThen::match_then(
+                        &rusty_regex_erased_pattern!($($pattern)+),
+                        text, position, &mut $crate::Captures::new(), &$crate::util::Accept);
+                    match (end, longest) {
+                        (Some(end), Some((_, longest_end))) if end <= longest_end => { }
+                        (Some(end), _) if end > position => longest = Some(($name::$token, end)),
+                        _ => { }
+                    }
+                )+
+                longest
+            }
+        }
+    };
+}
+
+// `rusty_regex! { name -> Struct = ... }`, once `rusty_regex_fields!`
+// has listed the typed groups, with their field types and how to parse
+// them. Each group records its match in a local `Cell` named after its
+// field.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_typed {
+    ([$(#[$($attr:tt)*])* $vis:vis $name:ident $record:ident]
+     [$(($field:ident : $ty:ty, $parse:ident))*]
+     $($tokens:tt)+) => {
+        #[derive(Clone, Debug, PartialEq)]
+        $vis struct $record {
+            $(pub $field: $ty,)*
+        }
+
+        $(#[$($attr)*])*
+        $vis fn $name(text: &str)
+                      -> Option<$crate::export::Result<$record, $crate::typed::FieldError>> {
+            $(let $field = $crate::export::Cell::new(None);)*
+            rusty_regex_match!(text; $($tokens)+)?;
+            Some((|| $crate::export::Result::Ok($record {
+                $($field: $crate::typed::$parse(stringify!($field), text, $field.get())?,)*
+            }))())
+        }
+    };
+}
+
+// The impls for `rusty_regex! { struct ... }` and `fragment`, which
+// copy the `cfg` attributes of the struct (collected in the leading
+// `[...]`). As a `RegexThen`, a `struct` captures its whole match and,
+// like a group, matches only at the current position.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_struct {
+    ([$($cfg:tt)*] #[cfg $($condition:tt)*] $($rest:tt)*) => {
+        rusty_regex_struct!([$($cfg)* #[cfg $($condition)*]] $($rest)*);
+    };
+
+    ([$($cfg:tt)*] #[$($attr:tt)*] $($rest:tt)*) => {
+        rusty_regex_struct!([$($cfg)*] $($rest)*);
+    };
+
+    ([$($cfg:tt)*] ; struct $name:ident; ^ $($tokens:tt)+) => {
+        rusty_regex_struct!([$($cfg)*] ; regex $name; $($tokens)+);
+
+        $($cfg)*
+        impl $crate::Matcher for $name {
+            fn captures_at<'text>(&self, text: &'text str, start: usize)
+                                  -> Option<$crate::export::Vec<$crate::Capture<'text>>> {
+                if start > 0 {
+                    return None;
+                }
+                $crate::matcher::captures_at(self, text, start)
+            }
+
+            fn is_match(&self, text: &str) -> bool {
+                rusty_regex_match!(is_match text; ^ $($tokens)+)
+            }
+
+            fn captures_into<'text>(&self,
+                                    text: &'text str,
+                                    captures: &mut $crate::CaptureBuf<'text>)
+                                    -> bool {
+                $crate::captures::captures_into(self, text, captures)
+            }
+
+            fn ends_at(&self, text: &str, start: usize) -> $crate::export::Vec<usize> {
+                if start > 0 {
+                    return $crate::export::Vec::new();
+                }
+                $crate::matcher::ends_at(self, text, start)
+            }
+        }
+    };
+
+    ([$($cfg:tt)*] ; regex $name:ident; $($tokens:tt)+) => {
+        $($cfg)*
+        impl $crate::RegexThen for $name {
+            fn match_then<'text, C>(&self,
+                                    text: &'text str,
+                                    position: usize,
+                                    captures: &mut $crate::Captures<'text>,
+                                    continuation: &C)
+                                    -> Option<usize>
+                where C: $crate::RegexContinuation
+            {
+                let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+                $crate::RegexThen::match_then(&regex, text, position, captures, continuation)
+            }
+
+            fn literals(&self) -> $crate::prefilter::Literals {
+                let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+                $crate::RegexThen::<str>::literals(&regex)
+            }
+
+            fn first_set(&self) -> $crate::prefilter::FirstSet {
+                let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+                $crate::RegexThen::<str>::first_set(&regex)
+            }
+        }
+    };
+
+    ([$($cfg:tt)*] ; fragment $name:ident; $($tokens:tt)+) => {
+        $($cfg)*
+        impl<T: ?Sized + $crate::Text> $crate::RegexThen<T> for $name {
+            #[inline]
+            fn match_then<'text, C>(&self,
+                                    text: &'text T,
+                                    position: usize,
+                                    captures: &mut $crate::Captures<'text, T>,
+                                    continuation: &C)
+                                    -> Option<usize>
+                where C: $crate::RegexContinuation<T>
+            {
+                let regex = rusty_regex_pattern!($($tokens)+);
+                $crate::RegexThen::match_then(&regex, text, position, captures, continuation)
+            }
+
+            fn literals(&self) -> $crate::prefilter::Literals {
+                $crate::RegexThen::<T>::literals(&rusty_regex_pattern!($($tokens)+))
+            }
+
+            fn first_set(&self) -> $crate::prefilter::FirstSet {
+                $crate::RegexThen::<T>::first_set(&rusty_regex_pattern!($($tokens)+))
+            }
+
+            fn may_capture(&self) -> bool {
+                $crate::RegexThen::<T>::may_capture(&rusty_regex_pattern!($($tokens)+))
+            }
+        }
+
+        $($cfg)*
+        impl $crate::util::IntoRegex for $name {
+            type Regex = $name;
+
+            fn into_regex(self) -> $name {
+                self
+            }
+        }
+    };
+
+    ([$($cfg:tt)*] ; struct $name:ident; $($tokens:tt)+) => {
+        rusty_regex_struct!([$($cfg)*] ; regex $name; $($tokens)+);
+
+        $($cfg)*
+        impl $crate::Matcher for $name {
+            fn captures_at<'text>(&self, text: &'text str, start: usize)
+                                  -> Option<$crate::export::Vec<$crate::Capture<'text>>> {
+                static PREFILTER: $crate::prefilter::Cache = $crate::prefilter::Cache::new();
+                $crate::matcher::captures_at(&$crate::util::Cached(&PREFILTER, *self), text, start)
+            }
+
+            fn is_match(&self, text: &str) -> bool {
+                rusty_regex_match!(is_match text; $($tokens)+)
+            }
+
+            fn captures_into<'text>(&self,
+                                    text: &'text str,
+                                    captures: &mut $crate::CaptureBuf<'text>)
+                                    -> bool {
+                static PREFILTER: $crate::prefilter::Cache = $crate::prefilter::Cache::new();
+                let regex = $crate::util::Cached(&PREFILTER, *self);
+                $crate::captures::captures_into(&regex, text, captures)
+            }
+
+            fn ends_at(&self, text: &str, start: usize) -> $crate::export::Vec<usize> {
+                $crate::matcher::ends_at(self, text, start)
+            }
+        }
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_match {
+    (history $text:expr; ^ $($tokens:tt)+) => {{
+        let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+        $crate::util::history(&regex, $text)
+    }};
+
+    (history $text:expr; $($tokens:tt)+) => {{
+        static PREFILTER: $crate::prefilter::Cache = $crate::prefilter::Cache::new();
+        let regex =
+            $crate::util::Cached(
+                &PREFILTER, $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+)));
+        $crate::util::history(&regex, $text)
+    }};
+
+    (buffered $text:expr, $captures:expr; ^ $($tokens:tt)+) => {{
+        let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+        $crate::captures::captures_into(&regex, $text, $captures)
+    }};
+
+    (buffered $text:expr, $captures:expr; $($tokens:tt)+) => {{
+        static PREFILTER: $crate::prefilter::Cache = $crate::prefilter::Cache::new();
+        let regex =
+            $crate::util::Cached(
+                &PREFILTER, $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+)));
+        $crate::captures::captures_into(&regex, $text, $captures)
+    }};
+
+    (is_match $text:expr; ^ $($tokens:tt)+) => {{
+        let regex = rusty_regex_erased_pattern!($($tokens)+);
+        $crate::RegexThen::match_then(&regex, $text, 0, &mut $crate::Captures::new(),
+                                      &$crate::util::Accept)
+            .is_some()
+    }};
+
+    (is_match $text:expr; $($tokens:tt)+) => {{
+        static PREFILTER: $crate::prefilter::Cache = $crate::prefilter::Cache::new();
+        let regex = $crate::util::Cached(&PREFILTER, rusty_regex_erased_pattern!($($tokens)+));
+        $crate::RegexThen::match_then(&regex, $text, 0, &mut $crate::Captures::new(),
+                                      &$crate::util::Accept)
+            .is_some()
+    }};
+
+    (const $text:expr; ^ $($tokens:tt)+) => {{
+        const PROGRAM: &[$crate::nfa::Inst] =
+            $crate::rusty_regex_validate!([$crate::rusty_regex_nfa] $($tokens)+);
+        $crate::nfa::is_match(PROGRAM, $text, true)
+    }};
+
+    (const $text:expr; $($tokens:tt)+) => {{
+        const PROGRAM: &[$crate::nfa::Inst] =
+            $crate::rusty_regex_validate!([$crate::rusty_regex_nfa] $($tokens)+);
+        $crate::nfa::is_match(PROGRAM, $text, false)
+    }};
+
+    (posix $text:expr; ^ $($tokens:tt)+) => {{
+        let mut captures = $crate::export::Vec::new();
+        let regex =
+            $crate::util::Longest($crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+)));
+        $crate::RegexThen::match_then(&regex, $text, 0, &mut captures, &$crate::util::Accept)
+            .map(|_| captures)
+    }};
+
+    (posix $text:expr; $($tokens:tt)+) => {{
+        let mut captures = $crate::export::Vec::new();
+        static PREFILTER: $crate::prefilter::Cache = $crate::prefilter::Cache::new();
+        let regex =
+            $crate::util::Cached(
+                &PREFILTER,
+                $crate::util::Longest(
+                    $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+))));
+        $crate::RegexThen::match_then(&regex, $text, 0, &mut captures, &$crate::util::Accept)
+            .map(|_| captures)
+    }};
+
+    ($text:expr; ^ $($tokens:tt)+) => {{
+        let mut captures = $crate::export::Vec::new();
+        let regex = $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+));
+        $crate::RegexThen::match_then(&regex, $text, 0, &mut captures, &$crate::util::Accept)
+            .map(|_| captures)
+    }};
+
+    // if no leading `^` is provided, search for the leftmost match
+    ($text:expr; $($tokens:tt)+) => {{
+        static PREFILTER: $crate::prefilter::Cache = $crate::prefilter::Cache::new();
+        let mut captures = $crate::export::Vec::new();
+        let regex =
+            $crate::util::Cached(
+                &PREFILTER, $crate::util::CaptureRe(rusty_regex_pattern!($($tokens)+)));
+        $crate::RegexThen::match_then(&regex, $text, 0, &mut captures, &$crate::util::Accept)
+            .map(|_| captures)
+    }};
+}
+
+// Checks the pattern, reporting malformed tokens with a proper error
+// message, before handing it to `rusty_regex_parse_tokens!`.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_pattern {
+    ($($tokens:tt)+) => {
+        $crate::rusty_regex_validate!([$crate::rusty_regex_parse_tokens] $($tokens)+)
+    };
+}
+
+// The pattern with every group made non-capturing, for when only
+// whether it matches is wanted.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_erased_pattern {
+    ($($tokens:tt)+) => {
+        $crate::rusty_regex_validate!([$crate::rusty_regex_erased_tokens] $($tokens)+)
+    };
+}
+
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_erased_tokens {
+    ($($tokens:tt)*) => {
+        $crate::rusty_regex_erase!([$crate::rusty_regex_parse_tokens] $($tokens)*)
+    };
 }
 
 #[macro_export]
@@ -71,8 +732,30 @@ macro_rules! rusty_regex_parse_token {
         rusty_regex_parse_tokens!($($token,)*)
     };
 
+    // a predicate group with its capture erased
+    ((? if $predicate:expr => $($token:tt)*)) => {
+        $crate::util::check(rusty_regex_parse_tokens!($($token,)*), $predicate)
+    };
+
+    (($field:ident : $ty:ty = $($token:tt)*)) => {
+        $crate::util::Field(&$field,
+                            $crate::util::GroupRe(rusty_regex_group!(Some(stringify!($field))),
+                                                  rusty_regex_parse_tokens!($($token,)*)))
+    };
+
+    (($name:ident = $($token:tt)*)) => {
+        $crate::util::GroupRe(rusty_regex_group!(Some(stringify!($name))),
+                              rusty_regex_parse_tokens!($($token,)*))
+    };
+
+    ((if $predicate:expr => $($token:tt)*)) => {
+        $crate::util::GroupRe(rusty_regex_group!(None),
+                              $crate::util::check(rusty_regex_parse_tokens!($($token,)*),
+                                                  $predicate))
+    };
+
     (($($token:tt)*)) => {
-        $crate::util::CaptureRe(rusty_regex_parse_tokens!($($token,)*))
+        $crate::util::GroupRe(rusty_regex_group!(None), rusty_regex_parse_tokens!($($token,)*))
     };
 
     ([$($token:tt)+]) => {
@@ -87,11 +770,31 @@ macro_rules! rusty_regex_parse_token {
         $crate::util::End
     };
 
+    ($name:ident) => {
+        $crate::util::IntoRegex::into_regex($name)
+    };
+
+    ({$($regex:tt)+}) => {
+        ($($regex)+)
+    };
+
+    // a string, or a byte string such as `b"\xFF"`
     ($literal:expr) => {
-        $crate::util::Literal($literal)
+        $crate::util::IntoRegex::into_regex($literal)
     };
 }
 
+// The `Group` of a group: a static of its own, so that each group
+// written in a pattern is distinct from every other.
+#[macro_export]
+#[doc(hidden)]
+macro_rules! rusty_regex_group {
+    ($name:expr) => {{
+        static GROUP: $crate::util::Group = $crate::util::Group { name: $name };
+        &GROUP
+    }};
+}
+
 #[macro_export]
 #[doc(hidden)]
 macro_rules! rusty_regex_parse_choices {
@@ -105,6 +808,11 @@ macro_rules! rusty_regex_parse_choices {
             rusty_regex_parse_choices!($($tokens,)*))
     };
 
+    // `:path::to::Class:`, grouped by `rusty_regex_validate!`
+    (($class:path), $($tokens:tt,)*) => {
+        $crate::util::OrChoice($class, rusty_regex_parse_choices!($($tokens,)*))
+    };
+
     (^, $($tokens:tt,)*) => {
         $crate::util::NotChoice(rusty_regex_parse_choices!($($tokens,)*))
     };
@@ -0,0 +1,184 @@
+//! A whole compiled regex as a value, as defined by
+//! `rusty_regex! { struct Name = ... }` or `Regex::new`, so that it can
+//! be passed around, stored, and used generically.
+
+use alloc::vec::Vec;
+use core::cell::RefCell;
+
+use captures::CaptureBuf;
+use util::{Accept, Mode};
+use {Capture, Captures, RegexContinuation, RegexThen};
+
+/// A complete regex over `str`. Only `captures_at` must be provided;
+/// the rest are built on it.
+pub trait Matcher {
+    /// The captures of the leftmost match beginning at or after
+    /// `start`: first the whole match, then each group that took part
+    /// in it. A regex anchored with `^` only matches at 0.
+    fn captures_at<'text>(&self, text: &'text str, start: usize) -> Option<Vec<Capture<'text>>>;
+
+    fn captures<'text>(&self, text: &'text str) -> Option<Vec<Capture<'text>>> {
+        self.captures_at(text, 0)
+    }
+
+    /// The leftmost match.
+    fn find<'text>(&self, text: &'text str) -> Option<Capture<'text>> {
+        self.captures(text).map(|captures| captures[0])
+    }
+
+    fn is_match(&self, text: &str) -> bool {
+        self.find(text).is_some()
+    }
+
+    /// Like `captures`, but into `captures`, replacing what it held, so
+    /// that its allocation can be reused from one call to the next.
+    /// Returns whether there was a match; if not, `captures` is empty.
+    fn captures_into<'text>(&self, text: &'text str, captures: &mut CaptureBuf<'text>) -> bool {
+        captures.captures.clear();
+        match self.captures(text) {
+            Some(found) => {
+                captures.captures.extend(found);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// The successive non-overlapping matches in `text`.
+    fn find_iter<'r, 'text>(&'r self, text: &'text str) -> FindIter<'r, 'text, Self> {
+        FindIter { matcher: self, text, start: Some(0) }
+    }
+
+    /// Every match in `text`, overlapping ones included: after each
+    /// match the search resumes one character past where it began, not
+    /// where it ended, so `"aa"` is found in `"aaaa"` at 0, 1 and 2.
+    fn find_overlapping_iter<'r, 'text>(&'r self, text: &'text str)
+                                        -> FindOverlappingIter<'r, 'text, Self> {
+        FindOverlappingIter { matcher: self, text, start: Some(0), all_ends: false, ends: vec![] }
+    }
+
+    /// Where each match beginning exactly at `start` ends, in
+    /// increasing order. The default only knows of the match that
+    /// `captures_at` finds; the regexes of this crate report every way
+    /// they can match there.
+    fn ends_at(&self, text: &str, start: usize) -> Vec<usize> {
+        self.captures_at(text, start)
+            .filter(|captures| captures[0].start == start)
+            .map_or(vec![], |captures| vec![captures[0].end])
+    }
+}
+
+/// Runs `regex`, which should capture the whole match as its first
+/// group, at `start`.
+pub fn captures_at<'text, R>(regex: &R, text: &'text str, start: usize)
+                             -> Option<Vec<Capture<'text>>>
+    where R: RegexThen
+{
+    let mut captures = vec![];
+    regex.match_then(text, start, &mut captures, &Accept).map(|_| captures)
+}
+
+/// Runs `regex`, which should capture the whole match as its first
+/// group, at exactly `start`, through every way it can match. Each
+/// entry into a repetition tries a position at most once, so nested
+/// repetitions such as `("a"+)+` take polynomial time rather than
+/// exponential; a generated function used as a regex still reports
+/// only its first match.
+pub fn ends_at<R>(regex: &R, text: &str, start: usize) -> Vec<usize>
+    where R: RegexThen
+{
+    let ends = Ends(RefCell::new(vec![]));
+    regex.match_then(text, start, &mut vec![], &ends);
+    let mut ends = ends.0.into_inner();
+    ends.sort_unstable();
+    ends.dedup();
+    ends
+}
+
+/// Notes where each match ends, then fails, to have every way of
+/// matching tried.
+struct Ends(RefCell<Vec<usize>>);
+
+impl RegexContinuation for Ends {
+    fn match_continue<'text>(&self,
+                             _text: &'text str,
+                             position: usize,
+                             _captures: &mut Captures<'text>)
+                             -> Option<usize>
+    {
+        self.0.borrow_mut().push(position);
+        None
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::Ends
+    }
+}
+
+/// Iterator returned by `Matcher::find_iter`.
+pub struct FindIter<'r, 'text, M: ?Sized + 'r> {
+    matcher: &'r M,
+    text: &'text str,
+    /// Where to search next, or `None` once there are no more matches.
+    start: Option<usize>,
+}
+
+impl<'r, 'text, M: ?Sized + Matcher> Iterator for FindIter<'r, 'text, M> {
+    type Item = Capture<'text>;
+
+    fn next(&mut self) -> Option<Capture<'text>> {
+        let found = self.matcher.captures_at(self.text, self.start?).map(|c| c[0]);
+        self.start = found.and_then(|found| {
+            if found.end > found.start {
+                Some(found.end)
+            } else {
+                // Never report the same empty match twice.
+                self.text[found.end..].chars().next().map(|c| found.end + c.len_utf8())
+            }
+        });
+        found
+    }
+}
+
+/// Iterator returned by `Matcher::find_overlapping_iter`.
+pub struct FindOverlappingIter<'r, 'text, M: ?Sized + 'r> {
+    matcher: &'r M,
+    text: &'text str,
+    /// Where to search next, or `None` once there are no more matches.
+    start: Option<usize>,
+    all_ends: bool,
+    /// With `all_ends`, the matches yet to report, last first.
+    ends: Vec<Capture<'text>>,
+}
+
+impl<'r, 'text, M: ?Sized + Matcher> FindOverlappingIter<'r, 'text, M> {
+    /// Reports, for each position where a match begins, every match
+    /// beginning there rather than just the one found first, shortest
+    /// first (using `Matcher::ends_at`).
+    pub fn all_ends(self) -> Self {
+        FindOverlappingIter { all_ends: true, ..self }
+    }
+}
+
+impl<'r, 'text, M: ?Sized + Matcher> Iterator for FindOverlappingIter<'r, 'text, M> {
+    type Item = Capture<'text>;
+
+    fn next(&mut self) -> Option<Capture<'text>> {
+        if let Some(found) = self.ends.pop() {
+            return Some(found);
+        }
+        let found = self.matcher.captures_at(self.text, self.start?).map(|c| c[0]);
+        self.start = found.and_then(|found| {
+            self.text[found.start..].chars().next().map(|c| found.start + c.len_utf8())
+        });
+        let found = found?;
+        if !self.all_ends {
+            return Some(found);
+        }
+        let text = self.text;
+        self.ends = self.matcher.ends_at(text, found.start).into_iter().rev()
+            .map(|end| Capture::new(text, found.start, end))
+            .collect();
+        self.ends.pop().or(Some(found))
+    }
+}
@@ -0,0 +1,147 @@
+//! Matching in a `const fn`, for `rusty_regex! { const name = ... }`.
+//! The pattern is compiled, by `rusty_regex_nfa!`, into a small program
+//! that `is_match` runs over the text once, keeping every state the
+//! pattern could be in at a time, so that it neither backtracks nor
+//! allocates. A program has at most `MAX_INSTS` instructions.
+
+/// One instruction of a compiled pattern. The consuming instructions
+/// match a character and go on to the next instruction.
+#[derive(Copy, Clone, Debug)]
+pub enum Inst {
+    Char(char),
+    /// Any character, like `.`.
+    Any,
+    /// A class, and whether it is negated.
+    Class(bool, &'static [ClassItem]),
+    /// Matches no text, only at the end.
+    End,
+    /// Goes on to both instructions.
+    Split(usize, usize),
+    Jump(usize),
+    Match,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum ClassItem {
+    Range(char, char),
+    /// `[:digit:]`.
+    Digit,
+    /// `[:space:]`.
+    Space,
+}
+
+/// How many instructions a program may have: one bit each in a `u128`.
+pub const MAX_INSTS: usize = 128;
+
+/// Whether `program` matches `text`: at its start if `anchored`, and
+/// anywhere in it otherwise.
+pub const fn is_match(program: &[Inst], text: &str, anchored: bool) -> bool {
+    assert!(program.len() <= MAX_INSTS, "a program has more than MAX_INSTS instructions");
+    let bytes = text.as_bytes();
+    let mut position = 0;
+    let mut states = add(program, 0, 0, bytes.is_empty());
+    loop {
+        if matched(program, states) {
+            return true;
+        }
+        if position == bytes.len() || (anchored && states == 0) {
+            return false;
+        }
+        let (c, len) = decode(bytes, position);
+        position += len;
+        let at_end = position == bytes.len();
+        states = step(program, states, c, at_end);
+        if !anchored {
+            states = add(program, states, 0, at_end);
+        }
+    }
+}
+
+/// `states` with `pc` and every instruction reachable from it without
+/// consuming a character added.
+const fn add(program: &[Inst], states: u128, pc: usize, at_end: bool) -> u128 {
+    if states & (1 << pc) != 0 {
+        return states;
+    }
+    let states = states | 1 << pc;
+    match program[pc] {
+        Inst::Jump(to) => add(program, states, to, at_end),
+        Inst::Split(a, b) => add(program, add(program, states, a, at_end), b, at_end),
+        Inst::End if at_end => add(program, states, pc + 1, at_end),
+        _ => states,
+    }
+}
+
+/// The states after consuming `c` in `states`.
+const fn step(program: &[Inst], states: u128, c: char, at_end: bool) -> u128 {
+    let mut next = 0;
+    let mut pc = 0;
+    while pc < program.len() {
+        if states & (1 << pc) != 0 {
+            let accepts = match program[pc] {
+                Inst::Char(expected) => c == expected,
+                Inst::Any => true,
+                Inst::Class(negated, items) => in_class(items, c) != negated,
+                _ => false,
+            };
+            if accepts {
+                next = add(program, next, pc + 1, at_end);
+            }
+        }
+        pc += 1;
+    }
+    next
+}
+
+const fn matched(program: &[Inst], states: u128) -> bool {
+    let mut pc = 0;
+    while pc < program.len() {
+        if states & (1 << pc) != 0 {
+            if let Inst::Match = program[pc] {
+                return true;
+            }
+        }
+        pc += 1;
+    }
+    false
+}
+
+const fn in_class(items: &[ClassItem], c: char) -> bool {
+    let mut i = 0;
+    while i < items.len() {
+        let found = match items[i] {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Digit => c.is_ascii_digit(),
+            ClassItem::Space => c.is_whitespace(),
+        };
+        if found {
+            return true;
+        }
+        i += 1;
+    }
+    false
+}
+
+/// The character starting at `position` in UTF-8 `bytes`, and its
+/// length.
+const fn decode(bytes: &[u8], position: usize) -> (char, usize) {
+    let first = bytes[position] as u32;
+    let (len, mut value) = if first < 0x80 {
+        (1, first)
+    } else if first < 0xe0 {
+        (2, first & 0x1f)
+    } else if first < 0xf0 {
+        (3, first & 0x0f)
+    } else {
+        (4, first & 0x07)
+    };
+    let mut i = 1;
+    while i < len {
+        value = value << 6 | (bytes[position + i] as u32 & 0x3f);
+        i += 1;
+    }
+    match char::from_u32(value) {
+        Some(c) => (c, len),
+        None => ('\u{fffd}', len),
+    }
+}
@@ -0,0 +1,301 @@
+//! Literal and first-character analysis plus fast substring scanning,
+//! used by unanchored searches to jump straight to positions where a
+//! match could begin.
+
+use core::cell::UnsafeCell;
+use core::convert::TryInto;
+use core::mem::{self, MaybeUninit};
+#[cfg(target_has_atomic = "8")]
+use core::sync::atomic::{AtomicU8, Ordering};
+
+use {CharRange, RegexThen, Text};
+
+/// Facts about the literal text that every match of a regex must
+/// contain. Computed bottom-up by `RegexThen::literals`; `None` always
+/// means "nothing is known", never "nothing is required".
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Literals {
+    /// Every match begins with this text.
+    pub prefix: Option<&'static str>,
+
+    /// Every match contains this text somewhere.
+    pub required: Option<&'static str>,
+
+    /// The regex never consumes any text (e.g., `END`), so whatever
+    /// follows it determines the prefix.
+    pub zero_width: bool,
+}
+
+impl Literals {
+    pub fn unknown() -> Literals {
+        Literals { prefix: None, required: None, zero_width: false }
+    }
+
+    pub fn zero_width() -> Literals {
+        Literals { prefix: None, required: None, zero_width: true }
+    }
+
+    pub fn literal(text: &'static str) -> Literals {
+        if text.is_empty() {
+            Literals::zero_width()
+        } else {
+            Literals { prefix: Some(text), required: Some(text), zero_width: false }
+        }
+    }
+
+    /// Literals for `self` followed by `next`.
+    pub fn then(self, next: Literals) -> Literals {
+        let required = match (self.required, next.required) {
+            (Some(a), Some(b)) => Some(if b.len() > a.len() { b } else { a }),
+            (a, b) => a.or(b),
+        };
+        Literals {
+            prefix: if self.zero_width { next.prefix } else { self.prefix },
+            required,
+            zero_width: self.zero_width && next.zero_width,
+        }
+    }
+}
+
+/// The set of characters that a match can begin with, as computed by
+/// `RegexThen::first_set`. ASCII characters are tracked exactly; all
+/// other characters share a single flag.
+#[derive(Copy, Clone)]
+pub struct FirstSet {
+    ascii: [bool; 128],
+    non_ascii: bool,
+
+    /// The regex may match without consuming anything, so any position
+    /// at all may begin a match.
+    pub nullable: bool,
+}
+
+impl FirstSet {
+    pub fn any() -> FirstSet {
+        FirstSet { ascii: [true; 128], non_ascii: true, nullable: true }
+    }
+
+    pub fn nullable() -> FirstSet {
+        FirstSet { ascii: [false; 128], non_ascii: false, nullable: true }
+    }
+
+    pub fn chars<CR: CharRange>(range: &CR) -> FirstSet {
+        let mut ascii = [false; 128];
+        for (b, entry) in ascii.iter_mut().enumerate() {
+            *entry = range.test(b as u8 as char);
+        }
+        FirstSet { ascii, non_ascii: range.may_match_non_ascii(), nullable: false }
+    }
+
+    pub fn literal(text: &str) -> FirstSet {
+        match text.chars().next() {
+            Some(c) => FirstSet::chars(&::util::CharChoice(c)),
+            None => FirstSet::nullable(),
+        }
+    }
+
+    /// First set for `self` followed by `next`.
+    pub fn then(self, next: FirstSet) -> FirstSet {
+        if self.nullable {
+            FirstSet { nullable: next.nullable, ..self.union(next) }
+        } else {
+            self
+        }
+    }
+
+    /// First set for `self` or nothing at all.
+    pub fn optional(self) -> FirstSet {
+        FirstSet { nullable: true, ..self }
+    }
+
+    pub fn union(self, other: FirstSet) -> FirstSet {
+        let mut ascii = self.ascii;
+        for (entry, &other) in ascii.iter_mut().zip(other.ascii.iter()) {
+            *entry |= other;
+        }
+        FirstSet {
+            ascii,
+            non_ascii: self.non_ascii || other.non_ascii,
+            nullable: self.nullable || other.nullable,
+        }
+    }
+
+    pub fn contains(&self, c: char) -> bool {
+        match self.ascii.get(c as usize) {
+            Some(&entry) => entry,
+            None => self.non_ascii,
+        }
+    }
+
+    /// A table indexed by the first byte of a UTF-8 encoded character.
+    /// Continuation bytes are never set, so every hit in a scan over
+    /// `str` bytes lands on a character boundary.
+    pub fn utf8_table(&self) -> [bool; 256] {
+        let mut table = [false; 256];
+        table[..128].copy_from_slice(&self.ascii);
+        for entry in &mut table[0xC0..] {
+            *entry = self.non_ascii;
+        }
+        table
+    }
+
+    /// A table indexed by byte, for text where any byte may begin a
+    /// character and every byte above `0x7F` decodes to a non-ASCII one.
+    pub fn byte_table(&self) -> [bool; 256] {
+        let mut table = [self.non_ascii; 256];
+        table[..128].copy_from_slice(&self.ascii);
+        table
+    }
+}
+
+/// What an unanchored search for a regex can skip ahead with: the
+/// regex's literals and, when it has no prefix, the bytes its matches
+/// can begin with. Computing it walks the whole regex, so a regex
+/// searched many times should compute it once.
+#[derive(Clone)]
+pub struct Prefilter {
+    prefix: Option<&'static [u8]>,
+    required: Option<&'static [u8]>,
+    first_bytes: Option<[bool; 256]>,
+}
+
+impl Prefilter {
+    pub fn new<T, R>(regex: &R) -> Prefilter
+        where T: ?Sized + Text, R: RegexThen<T>
+    {
+        let literals = regex.literals();
+        let prefix = literals.prefix.and_then(T::literal_bytes);
+        let first_set = match prefix {
+            Some(_) => None,
+            None => Some(regex.first_set()).filter(|first_set| !first_set.nullable),
+        };
+        Prefilter {
+            prefix,
+            required: literals.required.and_then(T::literal_bytes),
+            first_bytes: first_set.map(|first_set| T::start_table(&first_set)),
+        }
+    }
+
+    /// The first position at or after `start` where a match could
+    /// begin. `required_at` is where the required text was last found,
+    /// kept by the caller from one call to the next within a search.
+    pub fn next_start(&self, bytes: &[u8], start: usize, required_at: &mut Option<usize>)
+                      -> Option<usize> {
+        let mut start = start;
+        let rest = &bytes[start..];
+        if let Some(prefix) = self.prefix {
+            start += find(prefix, rest)?;
+        } else if let Some(ref first_bytes) = self.first_bytes {
+            start += rest.iter().position(|&b| first_bytes[b as usize])?;
+        }
+
+        if let Some(required) = self.required {
+            // A match starting at `start` must contain `required` at or
+            // after `start`; once there is no such occurrence, there is
+            // no match.
+            if required_at.is_none_or(|at| at < start) {
+                *required_at = Some(start + find(required, &bytes[start..])?);
+            }
+        }
+        Some(start)
+    }
+}
+
+/// A `Prefilter` computed on first use and then kept, for the functions
+/// `rusty_regex!` defines, which build their regex anew on every call.
+/// It never blocks: while one thread computes it, others go without.
+/// A cache must only be used for one regex and one kind of text.
+pub struct Cache {
+    #[cfg(target_has_atomic = "8")]
+    state: AtomicU8,
+    prefilter: UnsafeCell<MaybeUninit<Prefilter>>,
+}
+
+// `prefilter` is written once, before `state` becomes `READY`, and only
+// read after.
+unsafe impl Sync for Cache { }
+
+#[cfg(target_has_atomic = "8")]
+const EMPTY: u8 = 0;
+#[cfg(target_has_atomic = "8")]
+const BUSY: u8 = 1;
+#[cfg(target_has_atomic = "8")]
+const READY: u8 = 2;
+
+impl Cache {
+    pub const fn new() -> Cache {
+        Cache {
+            #[cfg(target_has_atomic = "8")]
+            state: AtomicU8::new(EMPTY),
+            prefilter: UnsafeCell::new(MaybeUninit::uninit()),
+        }
+    }
+
+    /// The prefilter for `regex`, or `None` if another thread is still
+    /// computing it, or if the target cannot keep it safely.
+    #[cfg(target_has_atomic = "8")]
+    pub fn get<T, R>(&self, regex: &R) -> Option<&Prefilter>
+        where T: ?Sized + Text, R: RegexThen<T>
+    {
+        if self.state.load(Ordering::Acquire) != READY {
+            self.state.compare_exchange(EMPTY, BUSY, Ordering::Acquire, Ordering::Relaxed).ok()?;
+            unsafe {
+                (*self.prefilter.get()).write(Prefilter::new::<T, R>(regex));
+            }
+            self.state.store(READY, Ordering::Release);
+        }
+        Some(unsafe { (*self.prefilter.get()).assume_init_ref() })
+    }
+
+    #[cfg(not(target_has_atomic = "8"))]
+    pub fn get<T, R>(&self, _regex: &R) -> Option<&Prefilter>
+        where T: ?Sized + Text, R: RegexThen<T>
+    {
+        None
+    }
+}
+
+impl Default for Cache {
+    fn default() -> Cache {
+        Cache::new()
+    }
+}
+
+const WORD: usize = mem::size_of::<usize>();
+const LO: usize = usize::MAX / 255;
+const HI: usize = LO << 7;
+
+/// Returns the index of the first occurrence of `needle` in
+/// `haystack`. Scans a word at a time, testing every byte of the word
+/// at once, and only falls back to a byte loop to pinpoint the hit.
+pub fn find_byte(needle: u8, haystack: &[u8]) -> Option<usize> {
+    let repeated = LO * (needle as usize);
+    let mut offset = 0;
+    for chunk in haystack.chunks_exact(WORD) {
+        let word = usize::from_ne_bytes(chunk.try_into().unwrap()) ^ repeated;
+        // Nonzero iff some byte of `word` is zero, i.e., matched `needle`.
+        if word.wrapping_sub(LO) & !word & HI != 0 {
+            break;
+        }
+        offset += WORD;
+    }
+    haystack[offset..].iter().position(|&b| b == needle).map(|i| offset + i)
+}
+
+/// Returns the index of the first occurrence of `needle` in
+/// `haystack`.
+pub fn find(needle: &[u8], haystack: &[u8]) -> Option<usize> {
+    let (&first, rest) = match needle.split_first() {
+        Some(split) => split,
+        None => return Some(0),
+    };
+    let mut offset = 0;
+    while haystack.len() - offset >= needle.len() {
+        let candidate = offset + find_byte(first, &haystack[offset..])?;
+        if haystack[candidate + 1..].starts_with(rest) {
+            return Some(candidate);
+        }
+        offset = candidate + 1;
+    }
+    None
+}
@@ -0,0 +1,57 @@
+//! Right-to-left matching. A forward search that only learns where a
+//! match ends (as a DFA would) can recover the leftmost start with
+//! `leftmost_start`; `ends_with` matches a regex anchored at the end of
+//! the text without trying every start position.
+//!
+//! Only `suffix` definitions use it. The engine has no forward pass
+//! that finds match ends on its own, so unanchored searches (`Unanchored`
+//! and every definition without `^`) still try each start position
+//! forward, skipping only those that the prefilter rules out.
+
+use core::cell::Cell;
+
+use {RegexRev, RegexRevContinuation, Text};
+
+/// Finds the smallest `start` such that `regex` matches exactly
+/// `text[start..end]`. Unlike ordinary matching, this does not stop at
+/// the first success but keeps backtracking for an earlier start. Each
+/// repetition skips the positions it has already been tried at, so
+/// nested repetitions take polynomial time; without the `alloc`
+/// feature they cannot be remembered, and patterns with many ambiguous
+/// ways to match may take exponential time.
+pub fn leftmost_start<T, R>(regex: &R, text: &T, end: usize) -> Option<usize>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    let leftmost = Leftmost { start: Cell::new(None) };
+    regex.match_rev_then(text, end, &leftmost);
+    leftmost.start.get()
+}
+
+/// Finds the smallest `start` such that `regex` matches exactly
+/// `text[start..]`.
+pub fn ends_with<T, R>(regex: &R, text: &T) -> Option<usize>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    leftmost_start(regex, text, text.as_bytes().len())
+}
+
+struct Leftmost {
+    start: Cell<Option<usize>>,
+}
+
+impl<T> RegexRevContinuation<T> for Leftmost
+    where T: ?Sized + Text
+{
+    fn match_rev_continue(&self,
+                          _text: &T,
+                          position: usize)
+                          -> Option<usize>
+    {
+        if self.start.get().is_none_or(|start| position < start) {
+            self.start.set(Some(position));
+        }
+
+        // Report failure to keep searching, unless nothing can beat this.
+        if position == 0 { Some(0) } else { None }
+    }
+}
@@ -0,0 +1,253 @@
+//! Regexes compiled at runtime, from the same conventional syntax that
+//! `regex!` accepts, into a tree of boxed `util` nodes.
+
+use std::boxed::Box;
+use std::fmt;
+use std::str::FromStr;
+use std::string::{String, ToString};
+use std::vec::Vec;
+
+use rusty_regex_syntax::{self as syntax, Class, ClassItem, Named, Node};
+
+use dynamic::DynRegex;
+use captures::{self, CaptureBuf};
+use matcher::{self, Matcher};
+use prefilter::{FirstSet, Literals, Prefilter};
+use util::{Accept, Alt, CaptureRe, Choice, End, PlusMax, PlusMin, Prefiltered, Question,
+           QuestionMin, RepeatMax, RepeatMin, StarMax, StarMin, Start, Unanchored, YesChoice};
+use util::named_choices;
+use {Capture, Captures, CharRange, RegexContinuation, RegexThen, Text};
+
+pub use rusty_regex_syntax::Error as ParseError;
+
+type BoxedRegex = Box<dyn DynRegex + Send + Sync>;
+
+/// A regex parsed from a string at runtime. It runs on the same engine
+/// as the functions `rusty_regex!` and `regex!` define, and its
+/// `Matcher` methods return exactly what such a function would.
+pub struct Regex {
+    pattern: String,
+    anchored: bool,
+
+    /// The pattern matched at a given position; searches run it in
+    /// `Prefiltered` unless `anchored`.
+    regex: BoxedRegex,
+    prefilter: Prefilter,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, ParseError> {
+        let parsed = syntax::parse(pattern)?;
+        let regex: BoxedRegex = Box::new(CaptureRe(node(&parsed.node)));
+        let prefilter = Prefilter::new::<str, _>(&regex);
+        Ok(Regex { pattern: pattern.to_string(), anchored: parsed.anchored, regex, prefilter })
+    }
+
+    /// The pattern this regex was parsed from.
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+}
+
+impl Matcher for Regex {
+    fn captures_at<'text>(&self, text: &'text str, start: usize) -> Option<Vec<Capture<'text>>> {
+        if self.anchored && start > 0 {
+            return None;
+        }
+        matcher::captures_at(self, text, start)
+    }
+
+    fn captures_into<'text>(&self, text: &'text str, captures: &mut CaptureBuf<'text>) -> bool {
+        captures::captures_into(self, text, captures)
+    }
+
+    fn ends_at(&self, text: &str, start: usize) -> Vec<usize> {
+        if self.anchored && start > 0 {
+            return vec![];
+        }
+        matcher::ends_at(&self.regex, text, start)
+    }
+}
+
+impl RegexThen for Regex {
+    fn match_then<'text,C>(&self,
+                           text: &'text str,
+                           position: usize,
+                           captures: &mut Captures<'text>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation
+    {
+        if self.anchored {
+            self.regex.match_then(text, position, captures, continuation)
+        } else {
+            Prefiltered(&self.prefilter, &self.regex)
+                .match_then(text, position, captures, continuation)
+        }
+    }
+
+    fn literals(&self) -> Literals {
+        if self.anchored {
+            self.regex.literals()
+        } else {
+            Unanchored(&self.regex).literals()
+        }
+    }
+
+    fn first_set(&self) -> FirstSet {
+        if self.anchored {
+            self.regex.first_set()
+        } else {
+            Unanchored(&self.regex).first_set()
+        }
+    }
+
+    fn may_capture(&self) -> bool {
+        self.regex.may_capture()
+    }
+}
+
+impl FromStr for Regex {
+    type Err = ParseError;
+
+    fn from_str(pattern: &str) -> Result<Regex, ParseError> {
+        Regex::new(pattern)
+    }
+}
+
+impl fmt::Debug for Regex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Regex({:?})", self.pattern)
+    }
+}
+
+fn node(node: &Node) -> BoxedRegex {
+    match *node {
+        Node::Literal(c) => Box::new(StringLiteral(c.to_string())),
+        Node::Any => Box::new(Choice(YesChoice)),
+        Node::Start => Box::new(Start),
+        Node::End => Box::new(End),
+        Node::Class(ref class) => Box::new(Choice(class.clone())),
+        Node::Group { capture: true, ref inner } => Box::new(CaptureRe(self::node(inner))),
+        Node::Group { capture: false, ref inner } => self::node(inner),
+        Node::Concat(ref items) => concat(items),
+        Node::Alt(ref branches) => {
+            let mut branches = branches.iter().rev().map(self::node);
+            let last = branches.next().unwrap();
+            branches.fold(last, |rest, branch| Box::new(Alt(branch, rest)))
+        }
+        Node::Repeat { ref inner, min, max, lazy } => repeat(inner, min, max, lazy),
+    }
+}
+
+/// A right-nested sequence ending in `Accept`, with runs of literal
+/// characters merged, just as `regex!` expands it.
+fn concat(items: &[Node]) -> BoxedRegex {
+    let mut parts: Vec<BoxedRegex> = vec![];
+    let mut literal = String::new();
+    for item in items {
+        match *item {
+            Node::Literal(c) => literal.push(c),
+            _ => {
+                if !literal.is_empty() {
+                    parts.push(Box::new(StringLiteral(literal.clone())));
+                    literal.clear();
+                }
+                parts.push(node(item));
+            }
+        }
+    }
+    if !literal.is_empty() {
+        parts.push(Box::new(StringLiteral(literal)));
+    }
+    parts.into_iter().rev().fold(Box::new(Accept), |rest, part| Box::new((part, rest)))
+}
+
+fn repeat(inner: &Node, min: usize, max: Option<usize>, lazy: bool) -> BoxedRegex {
+    match (min, max, lazy) {
+        (0, None, false) => return Box::new(StarMax(node(inner))),
+        (0, None, true) => return Box::new(StarMin(node(inner))),
+        (1, None, false) => return Box::new(PlusMax(node(inner))),
+        (1, None, true) => return Box::new(PlusMin(node(inner))),
+        (0, Some(1), false) => return Box::new(Question(node(inner))),
+        (0, Some(1), true) => return Box::new(QuestionMin(node(inner))),
+        _ => { }
+    }
+
+    if lazy {
+        Box::new(RepeatMin(node(inner), min, max))
+    } else {
+        Box::new(RepeatMax(node(inner), min, max))
+    }
+}
+
+/// Like `util::Literal`, but owning its text. Since `Literals` only
+/// holds `'static` text, it contributes nothing to literal analysis;
+/// searches still skip ahead using its first character.
+struct StringLiteral(String);
+
+impl<T> RegexThen<T> for StringLiteral
+    where T: ?Sized + Text
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        if text.has_literal_at(position, &self.0) {
+            continuation.match_continue(text, position + T::literal_len(&self.0), captures)
+        } else {
+            None
+        }
+    }
+
+    fn literals(&self) -> Literals {
+        Literals::unknown()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        FirstSet::literal(&self.0)
+    }
+
+    fn may_capture(&self) -> bool {
+        false
+    }
+}
+
+impl CharRange for Class {
+    fn test(&self, c: char) -> bool {
+        self.items.iter().any(|item| item_test(item, c)) != self.negated
+    }
+
+    fn may_match_non_ascii(&self) -> bool {
+        self.negated || self.items.iter().any(|item| {
+            match *item {
+                ClassItem::Char(c) => !c.is_ascii(),
+                ClassItem::Range(_, hi) => !hi.is_ascii(),
+                ClassItem::Named { name: Named::Digit, negated: false } => false,
+                ClassItem::Named { .. } => true,
+            }
+        })
+    }
+}
+
+fn item_test(item: &ClassItem, c: char) -> bool {
+    match *item {
+        ClassItem::Char(d) => c == d,
+        ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+        ClassItem::Named { name, negated } => {
+            let matched = match name {
+                Named::Alpha => named_choices::alpha.test(c),
+                Named::Digit => named_choices::digit.test(c),
+                Named::Space => named_choices::space.test(c),
+                Named::Word => {
+                    named_choices::alpha.test(c) || named_choices::digit.test(c) || c == '_'
+                }
+            };
+            matched != negated
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Matching many patterns against the same text, as defined by
+//! `rusty_regex_set!`.
+//!
+//! The set only adds a prefilter: the patterns still run one at a
+//! time, each with its own search on the backtracking engine. What
+//! they share is a single pass over the text that notes which bytes
+//! and which of their required literals occur in it. A pattern whose
+//! literal or first characters are missing is ruled out without
+//! looking at the text again, so most of a large set of mutually
+//! exclusive patterns costs next to nothing per text, but every
+//! pattern that passes is searched for separately.
+
+use std::vec::Vec;
+
+use prefilter::FirstSet;
+use RegexThen;
+
+/// One pattern of a set, with what is known about its matches.
+pub struct Pattern {
+    anchored: bool,
+
+    /// Text that every match contains (for an anchored pattern, that
+    /// every match begins with).
+    literal: Option<&'static str>,
+
+    /// The bytes a match can begin with, or `None` if it can match the
+    /// empty string.
+    first_bytes: Option<[bool; 256]>,
+
+    is_match: fn(&str) -> bool,
+}
+
+impl Pattern {
+    /// Analyzes `regex`, which `is_match` runs.
+    pub fn new<R: RegexThen>(anchored: bool, regex: &R, is_match: fn(&str) -> bool) -> Pattern {
+        let literals = regex.literals();
+        let literal = if anchored {
+            literals.prefix
+        } else {
+            match (literals.prefix, literals.required) {
+                (Some(a), Some(b)) => Some(if b.len() > a.len() { b } else { a }),
+                (a, b) => a.or(b),
+            }
+        };
+        let first_set: FirstSet = regex.first_set();
+        let first_bytes = if first_set.nullable { None } else { Some(first_set.utf8_table()) };
+        Pattern { anchored, literal, first_bytes, is_match }
+    }
+}
+
+/// The patterns of a `rusty_regex_set!` definition.
+pub struct RegexSet {
+    patterns: Vec<Pattern>,
+
+    /// The distinct literals of the unanchored patterns.
+    literals: Vec<&'static str>,
+
+    /// For each pattern, its index in `literals`, if it has one there.
+    pattern_literals: Vec<Option<usize>>,
+
+    /// Indices into `literals`, by their first byte.
+    by_first_byte: Vec<Vec<usize>>,
+
+    /// The unanchored patterns that can begin with each byte.
+    by_begin_byte: Vec<Vec<usize>>,
+}
+
+impl RegexSet {
+    pub fn new(patterns: Vec<Pattern>) -> RegexSet {
+        let mut literals: Vec<&'static str> = vec![];
+        let mut by_first_byte = vec![vec![]; 256];
+        let pattern_literals = patterns.iter().map(|pattern| {
+            let literal = match pattern.literal {
+                Some(literal) if !pattern.anchored => literal,
+                _ => return None,
+            };
+            Some(literals.iter().position(|&l| l == literal).unwrap_or_else(|| {
+                by_first_byte[literal.as_bytes()[0] as usize].push(literals.len());
+                literals.push(literal);
+                literals.len() - 1
+            }))
+        }).collect();
+        let mut by_begin_byte = vec![vec![]; 256];
+        for (index, pattern) in patterns.iter().enumerate() {
+            match pattern.first_bytes {
+                Some(ref table) if !pattern.anchored => {
+                    for b in (0..256).filter(|&b| table[b]) {
+                        by_begin_byte[b].push(index);
+                    }
+                }
+                _ => {}
+            }
+        }
+        RegexSet { patterns, literals, pattern_literals, by_first_byte, by_begin_byte }
+    }
+
+    pub fn len(&self) -> usize {
+        self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// The indices of the patterns that match somewhere in `text`, in
+    /// the order the patterns were given.
+    pub fn matches(&self, text: &str) -> Vec<usize> {
+        let scan = self.scan(text);
+        (0..self.patterns.len()).filter(|&index| self.is_match_at(&scan, index, text)).collect()
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        let scan = self.scan(text);
+        (0..self.patterns.len()).any(|index| self.is_match_at(&scan, index, text))
+    }
+
+    /// The one pass over `text` that all the patterns share.
+    fn scan(&self, text: &str) -> Scan {
+        let bytes = text.as_bytes();
+        let mut scan = Scan {
+            present: [false; 256],
+            found: vec![false; self.literals.len()],
+            may_begin: vec![false; self.patterns.len()],
+        };
+        for (i, &b) in bytes.iter().enumerate() {
+            if !scan.present[b as usize] {
+                scan.present[b as usize] = true;
+                for &index in &self.by_begin_byte[b as usize] {
+                    scan.may_begin[index] = true;
+                }
+            }
+            for &literal in &self.by_first_byte[b as usize] {
+                if !scan.found[literal] && bytes[i..].starts_with(self.literals[literal].as_bytes()) {
+                    scan.found[literal] = true;
+                }
+            }
+        }
+        scan
+    }
+
+    /// Whether pattern `index` matches `text`, running it only if the
+    /// scan allows it.
+    fn is_match_at(&self, scan: &Scan, index: usize, text: &str) -> bool {
+        let pattern = &self.patterns[index];
+        let literal_found = match self.pattern_literals[index] {
+            Some(literal) => scan.found[literal],
+            None if pattern.anchored => pattern.literal.is_none_or(|l| text.starts_with(l)),
+            None => true,
+        };
+        let may_begin = match pattern.first_bytes {
+            Some(ref table) if pattern.anchored => {
+                text.as_bytes().first().is_some_and(|&b| table[b as usize])
+            }
+            Some(_) => scan.may_begin[index],
+            None => true,
+        };
+        literal_found && may_begin && (pattern.is_match)(text)
+    }
+}
+
+/// Which bytes and which literals occur in a text.
+struct Scan {
+    present: [bool; 256],
+    found: Vec<bool>,
+
+    /// For each unanchored pattern, whether a byte it can begin with
+    /// occurs.
+    may_begin: Vec<bool>,
+}
@@ -0,0 +1,420 @@
+//! Searching a `BufRead` (or an iterator of chunks) without loading it
+//! all into memory.
+//!
+//! The stream is buffered only from the current start position onward.
+//! Each match attempt runs against `Partial`, which notes whenever the
+//! attempt needed to look past the bytes buffered so far; only then is
+//! more input read and the attempt retried. As a result, a pattern whose
+//! matches have bounded length never buffers much more than that length
+//! plus one read.
+
+use std::cell::Cell;
+use std::char;
+use std::cmp;
+use std::io::{self, BufRead, Read};
+use std::marker::PhantomData;
+use std::str;
+use std::vec::Vec;
+
+use prefilter::{self, FirstSet};
+use util::Accept;
+use text::{self, ByteText, Utf8Bytes};
+use {Capture, RegexThen, Text};
+
+/// How the bytes of a stream decode into characters: `Latin1`, one per
+/// byte like `bytes` mode, or `Utf8`, like `bytes utf8`.
+pub trait Decoding {
+    /// The character at the start of `bytes` and its length, or `None`
+    /// if `bytes` ends before the character does.
+    fn decode(bytes: &[u8]) -> Option<(char, usize)>;
+
+    /// The character at the end of `bytes` and its length, or `None` if
+    /// `bytes` is empty.
+    fn decode_before(bytes: &[u8]) -> Option<(char, usize)>;
+
+    /// How many bytes `c` is encoded as.
+    fn char_len(c: char) -> usize;
+
+    /// The bytes `literal` is encoded as, if they can be borrowed.
+    fn literal_bytes(literal: &'static str) -> Option<&'static [u8]>;
+}
+
+/// Each byte is one character, the Latin-1 one above `0x7F`.
+pub enum Latin1 { }
+
+impl Decoding for Latin1 {
+    fn decode(bytes: &[u8]) -> Option<(char, usize)> {
+        bytes.first().map(|&b| (b as char, 1))
+    }
+
+    fn decode_before(bytes: &[u8]) -> Option<(char, usize)> {
+        bytes.last().map(|&b| (b as char, 1))
+    }
+
+    fn char_len(_c: char) -> usize {
+        1
+    }
+
+    fn literal_bytes(literal: &'static str) -> Option<&'static [u8]> {
+        text::latin1_bytes(literal)
+    }
+}
+
+/// UTF-8, with each byte of an invalid sequence decoding on its own to
+/// `U+FFFD REPLACEMENT CHARACTER`, as in `Utf8Bytes`.
+pub enum Utf8 { }
+
+impl Decoding for Utf8 {
+    fn decode(bytes: &[u8]) -> Option<(char, usize)> {
+        let bytes = &bytes[..cmp::min(bytes.len(), 4)];
+        let valid = match str::from_utf8(bytes) {
+            Ok(valid) => valid,
+            // Invalid, rather than cut off by the end of `bytes`.
+            Err(error) if error.valid_up_to() == 0 && error.error_len().is_some() => {
+                return Some((char::REPLACEMENT_CHARACTER, 1));
+            }
+            Err(error) => str::from_utf8(&bytes[..error.valid_up_to()]).unwrap(),
+        };
+        valid.chars().next().map(|c| (c, c.len_utf8()))
+    }
+
+    fn decode_before(bytes: &[u8]) -> Option<(char, usize)> {
+        Utf8Bytes::new(bytes).char_before(bytes.len())
+    }
+
+    fn char_len(c: char) -> usize {
+        c.len_utf8()
+    }
+
+    fn literal_bytes(literal: &'static str) -> Option<&'static [u8]> {
+        Some(literal.as_bytes())
+    }
+}
+
+/// The bytes of a stream buffered so far, decoded as `D` says: by
+/// default one byte per character, like `bytes` mode.
+pub struct Partial<'a, D = Latin1> {
+    bytes: &'a [u8],
+    at_eof: bool,
+    hit_end: Cell<bool>,
+    decoding: PhantomData<D>,
+}
+
+impl<'a, D> Partial<'a, D> {
+    pub fn new(bytes: &'a [u8], at_eof: bool) -> Partial<'a, D> {
+        Partial { bytes, at_eof, hit_end: Cell::new(false), decoding: PhantomData }
+    }
+
+    /// Whether some match attempt looked past the buffered bytes, so
+    /// that its outcome might change once more input arrives.
+    pub fn hit_end(&self) -> bool {
+        self.hit_end.get()
+    }
+
+    fn missing(&self) -> bool {
+        if !self.at_eof {
+            self.hit_end.set(true);
+        }
+        false
+    }
+}
+
+impl<'a, D: Decoding> Text for Partial<'a, D> {
+    fn as_bytes(&self) -> &[u8] {
+        self.bytes
+    }
+
+    fn char_at(&self, position: usize) -> Option<(char, usize)> {
+        let rest = &self.bytes[position..];
+        match D::decode(rest) {
+            Some(decoded) => Some(decoded),
+            // A character cut off by the end of the stream.
+            None if self.at_eof && !rest.is_empty() => Some((char::REPLACEMENT_CHARACTER, 1)),
+            None => {
+                self.missing();
+                None
+            }
+        }
+    }
+
+    fn char_before(&self, position: usize) -> Option<(char, usize)> {
+        D::decode_before(&self.bytes[..position])
+    }
+
+    fn has_literal_at(&self, mut position: usize, literal: &str) -> bool {
+        // Decoded character by character, so that running out of input
+        // is noticed.
+        for c in literal.chars() {
+            match self.char_at(position) {
+                Some((d, len)) if d == c && len == D::char_len(c) => position += len,
+                _ => return false,
+            }
+        }
+        true
+    }
+
+    fn has_literal_before(&self, mut position: usize, literal: &str) -> bool {
+        literal.chars().rev().all(|c| {
+            match self.char_before(position) {
+                Some((d, len)) if d == c && len == D::char_len(c) => {
+                    position -= len;
+                    true
+                }
+                _ => false,
+            }
+        })
+    }
+
+    fn literal_len(literal: &str) -> usize {
+        literal.chars().map(D::char_len).sum()
+    }
+
+    fn literal_bytes(literal: &'static str) -> Option<&'static [u8]> {
+        D::literal_bytes(literal)
+    }
+
+    fn is_end(&self, position: usize) -> bool {
+        position == self.bytes.len() && (self.at_eof || self.missing())
+    }
+
+    fn start_table(first_set: &FirstSet) -> [bool; 256] {
+        first_set.byte_table()
+    }
+}
+
+impl<'a, D: Decoding> ByteText for Partial<'a, D> {
+    fn has_bytes_at(&self, position: usize, bytes: &[u8]) -> bool {
+        let rest = &self.bytes[position..];
+        if rest.len() >= bytes.len() {
+            rest.starts_with(bytes)
+        } else {
+            bytes.starts_with(rest) && self.missing()
+        }
+    }
+}
+
+/// A capture found in a stream. Offsets are absolute, counted from the
+/// start of the stream.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct StreamCapture {
+    pub start: u64,
+    pub end: u64,
+    pub bytes: Vec<u8>,
+}
+
+/// Iterator over the successive non-overlapping matches of `P` in a
+/// stream decoded as `D`, as generated by `rusty_regex! { stream name =
+/// ... }` (or `stream utf8`).
+pub struct Matches<R, P, D = Latin1> {
+    reader: R,
+    regex: P,
+    anchored: bool,
+    prefix: Option<&'static [u8]>,
+    first_bytes: Option<[bool; 256]>,
+    buffer: Vec<u8>,
+    /// Absolute offset of `buffer[0]`.
+    offset: u64,
+    /// Index into `buffer` of the next start position to try. May be
+    /// one past the end after an empty match.
+    start: usize,
+    at_eof: bool,
+    done: bool,
+    decoding: PhantomData<D>,
+}
+
+impl<R, P, D> Matches<R, P, D>
+    where R: BufRead, P: for<'a> RegexThen<Partial<'a, D>>, D: Decoding
+{
+    /// Finds every match of `regex`, which should capture the whole
+    /// match as its first group.
+    pub fn new(reader: R, regex: P) -> Matches<R, P, D> {
+        let prefix = regex.literals().prefix.and_then(D::literal_bytes);
+        let first_set = regex.first_set();
+        let first_bytes = match prefix {
+            None if !first_set.nullable => Some(first_set.byte_table()),
+            _ => None,
+        };
+        Matches {
+            reader,
+            regex,
+            anchored: false,
+            prefix,
+            first_bytes,
+            buffer: vec![],
+            offset: 0,
+            start: 0,
+            at_eof: false,
+            done: false,
+            decoding: PhantomData,
+        }
+    }
+
+    /// Finds only a match beginning at the very start of the stream.
+    pub fn anchored(reader: R, regex: P) -> Matches<R, P, D> {
+        Matches { anchored: true, ..Matches::new(reader, regex) }
+    }
+
+    /// Drops everything before `self.start` and appends the next chunk.
+    fn read_more(&mut self) -> io::Result<()> {
+        let drained = cmp::min(self.start, self.buffer.len());
+        self.buffer.drain(..drained);
+        self.offset += drained as u64;
+        self.start -= drained;
+
+        let chunk_len = {
+            let chunk = self.reader.fill_buf()?;
+            self.buffer.extend_from_slice(chunk);
+            chunk.len()
+        };
+        self.reader.consume(chunk_len);
+        self.at_eof = chunk_len == 0;
+        Ok(())
+    }
+
+    /// Advances `self.start` over positions where no match can begin,
+    /// judging only from the bytes buffered so far.
+    fn skip(&mut self) {
+        let rest = &self.buffer[self.start..];
+        if let Some(prefix) = self.prefix {
+            self.start += match prefilter::find(prefix, rest) {
+                Some(offset) => offset,
+                // Keep a tail that could be the beginning of `prefix`.
+                None => rest.len().saturating_sub(prefix.len() - 1),
+            };
+        } else if let Some(ref first_bytes) = self.first_bytes {
+            self.start += rest.iter().position(|&b| first_bytes[b as usize]).unwrap_or(rest.len());
+        }
+    }
+
+    fn next_match(&mut self) -> io::Result<Option<Vec<StreamCapture>>> {
+        loop {
+            if self.start > self.buffer.len() {
+                if self.at_eof {
+                    return Ok(None);
+                }
+                self.read_more()?;
+                continue;
+            }
+
+            if !self.anchored {
+                self.skip();
+            }
+
+            let (result, hit_end) = {
+                let partial = Partial::<D>::new(&self.buffer, self.at_eof);
+                let mut captures = vec![];
+                let offset = self.offset;
+                let result = self.regex
+                                 .match_then(&partial, self.start, &mut captures, &Accept)
+                                 .map(|end| {
+                                     let found = captures.iter()
+                                                         .map(|&c| to_stream_capture(offset, c))
+                                                         .collect::<Vec<_>>();
+                                     (end, found)
+                                 });
+                (result, partial.hit_end())
+            };
+
+            if hit_end {
+                self.read_more()?;
+                continue;
+            }
+
+            match result {
+                Some((end, found)) => {
+                    // Never report the same empty match twice.
+                    self.start = if end == self.start { end + 1 } else { end };
+                    self.done = self.anchored;
+                    return Ok(Some(found));
+                }
+                None if self.anchored => {
+                    return Ok(None);
+                }
+                None => {
+                    // On to the next character; at the end of the
+                    // buffer, a cut-off one is left for `read_more`.
+                    self.start += D::decode(&self.buffer[self.start..]).map_or(1, |(_, len)| len);
+                }
+            }
+        }
+    }
+}
+
+fn to_stream_capture<D: Decoding>(offset: u64, capture: Capture<Partial<D>>) -> StreamCapture {
+    StreamCapture {
+        start: offset + capture.start as u64,
+        end: offset + capture.end as u64,
+        bytes: capture.as_bytes().to_vec(),
+    }
+}
+
+impl<R, P, D> Iterator for Matches<R, P, D>
+    where R: BufRead, P: for<'a> RegexThen<Partial<'a, D>>, D: Decoding
+{
+    type Item = io::Result<Vec<StreamCapture>>;
+
+    fn next(&mut self) -> Option<io::Result<Vec<StreamCapture>>> {
+        if self.done {
+            return None;
+        }
+        let result = self.next_match();
+        if let Ok(None) | Err(_) = result {
+            self.done = true;
+        }
+        result.transpose()
+    }
+}
+
+/// Adapts an iterator of byte chunks into a `BufRead`, for input that
+/// arrives in pieces (e.g., network packets).
+pub struct ChunkReader<I: Iterator> {
+    chunks: I,
+    current: Option<I::Item>,
+    position: usize,
+}
+
+impl<I> ChunkReader<I>
+    where I: Iterator, I::Item: AsRef<[u8]>
+{
+    pub fn new<C>(chunks: C) -> ChunkReader<I>
+        where C: IntoIterator<IntoIter = I, Item = I::Item>
+    {
+        ChunkReader { chunks: chunks.into_iter(), current: None, position: 0 }
+    }
+}
+
+impl<I> Read for ChunkReader<I>
+    where I: Iterator, I::Item: AsRef<[u8]>
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let len = {
+            let chunk = self.fill_buf()?;
+            let len = chunk.len().min(buf.len());
+            buf[..len].copy_from_slice(&chunk[..len]);
+            len
+        };
+        self.consume(len);
+        Ok(len)
+    }
+}
+
+impl<I> BufRead for ChunkReader<I>
+    where I: Iterator, I::Item: AsRef<[u8]>
+{
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.current.as_ref().is_none_or(|c| self.position == c.as_ref().len()) {
+            match self.chunks.next() {
+                Some(chunk) => {
+                    self.current = Some(chunk);
+                    self.position = 0;
+                }
+                None => return Ok(&[]),
+            }
+        }
+        Ok(&self.current.as_ref().unwrap().as_ref()[self.position..])
+    }
+
+    fn consume(&mut self, amount: usize) {
+        self.position += amount;
+    }
+}
@@ -1,4 +1,8 @@
-use Capture;
+use std::prelude::v1::*;
+
+use captures::CaptureArray;
+use {lexer, regex, util, Capture, CaptureBuf, FromCaptures, FromHistory, Matcher, Regex,
+     RegexThen};
 
 fn to_captures<'text>(c: Vec<Capture<'text>>) -> Vec<&'text str> {
     c.iter()
@@ -70,15 +74,15 @@ rusty_regex! { star_plus_re = ^ ("hi"*) ("hi"+) }
 #[test]
 fn star_plus() {
     assert_eq!(star_plus_re("hihihi").unwrap(), vec![
-        Capture { text: "hihihi", start: 0, end: 6 },
-        Capture { text: "hihihi", start: 0, end: 4 },
-        Capture { text: "hihihi", start: 4, end: 6 }
+        Capture::new("hihihi", 0, 6),
+        Capture::new("hihihi", 0, 4),
+        Capture::new("hihihi", 4, 6)
         ]);
 
     assert_eq!(star_plus_re("hi").unwrap(), vec![
-        Capture { text: "hi", start: 0, end: 2 },
-        Capture { text: "hi", start: 0, end: 0 },
-        Capture { text: "hi", start: 0, end: 2 }
+        Capture::new("hi", 0, 2),
+        Capture::new("hi", 0, 0),
+        Capture::new("hi", 0, 2)
         ]);
 }
 
@@ -89,15 +93,15 @@ rusty_regex! { star_min_plus_re = ^ ("hi"*?) ("hi"+) }
 #[test]
 fn star_min_plus() {
     assert_eq!(star_min_plus_re("hihihi").unwrap(), vec![
-        Capture { text: "hihihi", start: 0, end: 6 },
-        Capture { text: "hihihi", start: 0, end: 0 },
-        Capture { text: "hihihi", start: 0, end: 6 }
+        Capture::new("hihihi", 0, 6),
+        Capture::new("hihihi", 0, 0),
+        Capture::new("hihihi", 0, 6)
         ]);
 
     assert_eq!(star_min_plus_re("hi").unwrap(), vec![
-        Capture { text: "hi", start: 0, end: 2 },
-        Capture { text: "hi", start: 0, end: 0 },
-        Capture { text: "hi", start: 0, end: 2 }
+        Capture::new("hi", 0, 2),
+        Capture::new("hi", 0, 0),
+        Capture::new("hi", 0, 2)
         ]);
 }
 
@@ -108,15 +112,15 @@ rusty_regex! { star_min_hi_plus_ho_end_re = ^ (['a' 'c']*?) (['b' 'c']+) END }
 #[test]
 fn star_min_hi_plus_ho() {
     assert_eq!(star_min_hi_plus_ho_end_re("aacbbc").unwrap(), vec![
-        Capture { text: "aacbbc", start: 0, end: 6 },
-        Capture { text: "aacbbc", start: 0, end: 2 },
-        Capture { text: "aacbbc", start: 2, end: 6 },
+        Capture::new("aacbbc", 0, 6),
+        Capture::new("aacbbc", 0, 2),
+        Capture::new("aacbbc", 2, 6),
         ]);
 
     assert_eq!(star_min_hi_plus_ho_end_re("aacabbc").unwrap(), vec![
-        Capture { text: "aacabbc", start: 0, end: 7 },
-        Capture { text: "aacabbc", start: 0, end: 4 },
-        Capture { text: "aacabbc", start: 4, end: 7 },
+        Capture::new("aacabbc", 0, 7),
+        Capture::new("aacabbc", 0, 4),
+        Capture::new("aacabbc", 4, 7),
         ]);
 }
 
@@ -127,9 +131,9 @@ rusty_regex! { plus_plus_re = ^ ("hi"+) ("hi"+) }
 #[test]
 fn plus_plus() {
     assert_eq!(plus_plus_re("hihihi").unwrap(), vec![
-        Capture { text: "hihihi", start: 0, end: 6 },
-        Capture { text: "hihihi", start: 0, end: 4 },
-        Capture { text: "hihihi", start: 4, end: 6 }
+        Capture::new("hihihi", 0, 6),
+        Capture::new("hihihi", 0, 4),
+        Capture::new("hihihi", 4, 6)
         ]);
 
     assert!(plus_plus_re("hi").is_none());
@@ -142,13 +146,24 @@ rusty_regex! { paren_no_cap_re = ^ (?:"hi" "hi")+ ("hi"+) }
 #[test]
 fn paren_no_cap() {
     assert_eq!(paren_no_cap_re("hihihi").unwrap(), vec![
-        Capture { text: "hihihi", start: 0, end: 6 },
-        Capture { text: "hihihi", start: 4, end: 6 }
+        Capture::new("hihihi", 0, 6),
+        Capture::new("hihihi", 4, 6)
         ]);
 }
 
 ///////////////////////////////////////////////////////////////////////////
 
+rusty_regex! { question_re = ^ (("x") "y")? ("x" [:alpha:]) }
+
+#[test]
+fn question_drops_failed_captures() {
+    // `("x")` matches before `"y"` fails; its capture must not remain.
+    assert_eq!(to_captures(question_re("xz").unwrap()), vec!["xz", "xz"]);
+    assert_eq!(to_captures(question_re("xyxz").unwrap()), vec!["xyxz", "xy", "x", "xz"]);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
 rusty_regex! { char_range_re = ^ ['a' 'b' 'c']+ }
 
 #[test]
@@ -242,3 +257,792 @@ fn uri() {
                vec!["http://foo/bar/baz", "http", "foo", "/bar/baz"]);
 }
 
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! { error_line_re = "ERROR" [:space:]+ ([^' ']+) }
+
+#[test]
+fn literal_prefix() {
+    assert_eq!(to_captures(error_line_re("12:00 ERROR  disk full").unwrap()),
+               vec!["ERROR  disk", "disk"]);
+    assert_eq!(to_captures(error_line_re("ERRO ERROR x").unwrap()),
+               vec!["ERROR x", "x"]);
+    assert_eq!(to_captures(error_line_re("é ERROR ü").unwrap()),
+               vec!["ERROR ü", "ü"]);
+    assert!(error_line_re("ERROR").is_none());
+    assert!(error_line_re("WARN fine").is_none());
+}
+
+#[test]
+fn literal_analysis() {
+    use prefilter::Literals;
+    use util::{CaptureRe, Choice, Literal, Question, StarMax, Unanchored, YesChoice};
+
+    let literals = RegexThen::<str>::literals(&(Question(Literal("x")), (Literal("ab"), Literal("cde"))));
+    assert_eq!(literals, Literals { prefix: None, required: Some("cde"), zero_width: false });
+
+    let literals = RegexThen::<str>::literals(&(Literal(""), CaptureRe((Literal("ab"), StarMax(Literal("x"))))));
+    assert_eq!(literals, Literals { prefix: Some("ab"), required: Some("ab"), zero_width: false });
+
+    let literals = RegexThen::<str>::literals(&Unanchored((Choice(YesChoice), Literal("://"))));
+    assert_eq!(literals, Literals { prefix: None, required: Some("://"), zero_width: false });
+}
+
+#[test]
+fn find_literal() {
+    use prefilter::{find, find_byte};
+
+    let haystack = b"the quick brown fox jumps over the lazy dog";
+    assert_eq!(find_byte(b'z', haystack), Some(37));
+    assert_eq!(find_byte(b'!', haystack), None);
+    assert_eq!(find(b"the", haystack), Some(0));
+    assert_eq!(find(b"the lazy", haystack), Some(31));
+    assert_eq!(find(b"dogs", haystack), None);
+    assert_eq!(find(b"", haystack), Some(0));
+}
+
+#[test]
+fn cached_prefilter() {
+    use prefilter::{Cache, Prefilter};
+    use util::{Accept, Cached, Literal, Prefiltered};
+
+    static CACHE: Cache = Cache::new();
+    let regex = (Literal("ab"), Accept);
+    for _ in 0..2 {
+        let cached = Cached(&CACHE, (Literal("ab"), Accept));
+        assert_eq!(cached.match_then("xxab", 0, &mut vec![], &Accept), Some(4));
+        assert_eq!(cached.match_then("xxa", 0, &mut vec![], &Accept), None);
+    }
+
+    let prefilter = Prefilter::new::<str, _>(&regex);
+    assert_eq!(Prefiltered(&prefilter, regex).match_then("abxab", 1, &mut vec![], &Accept),
+               Some(5));
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! { decimal_re = ([:digit:]+) "." ([:digit:]*) }
+
+#[test]
+fn first_set_skip() {
+    assert_eq!(to_captures(decimal_re("pi is about 3.14").unwrap()),
+               vec!["3.14", "3", "14"]);
+    assert_eq!(to_captures(decimal_re("ünïcödé 10.").unwrap()),
+               vec!["10.", "10", ""]);
+    assert!(decimal_re("1 2 3").is_none());
+}
+
+#[test]
+fn first_set_analysis() {
+    use util::{Choice, End, Literal, Question, RangeChoice, StarMax};
+    use util::named_choices::digit;
+
+    let first_set = RegexThen::<str>::first_set(&(Question(Literal("-")), Choice(digit)));
+    assert!(first_set.contains('-') && first_set.contains('7'));
+    assert!(!first_set.contains('a') && !first_set.contains('é') && !first_set.nullable);
+
+    let first_set = RegexThen::<str>::first_set(&(End, Choice(RangeChoice('a', 'é'))));
+    assert!(first_set.contains('b') && first_set.contains('ü') && !first_set.contains('A'));
+
+    let first_set = RegexThen::<str>::first_set(&StarMax(Literal("x")));
+    assert!(first_set.contains('x') && first_set.nullable);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! { bytes packet_re = "\x01" (. .) ([^ '\x00']*) "\x00" }
+
+#[test]
+fn bytes() {
+    let packet = b"\xff\xfe\x01\x80\x7fdata\xc3\x00tail";
+    let captures = packet_re(packet).unwrap();
+    assert_eq!(captures.iter().map(|c| c.as_bytes()).collect::<Vec<_>>(),
+               vec![&b"\x01\x80\x7fdata\xc3\x00"[..], b"\x80\x7f", b"data\xc3"]);
+    assert_eq!((captures[1].start, captures[1].end), (3, 5));
+    assert!(packet_re(b"\x01\xff").is_none());
+}
+
+const ELF: &[u8] = b"\x7fELF";
+
+rusty_regex! {
+    bytes latin1_re = "é" (['é'] "ÿ") b"\xc3\xa9" ELF;
+    bytes utf8 utf8_bytes_re = "é" b"\xff" (.)
+}
+
+#[test]
+fn byte_literals() {
+    // Literals read a byte as one character, just as classes do.
+    let captures = latin1_re(b"x\xe9\xe9\xff\xc3\xa9\x7fELF").unwrap();
+    assert_eq!(captures[0].as_bytes(), b"\xe9\xe9\xff\xc3\xa9\x7fELF");
+    assert_eq!(captures[1].as_bytes(), b"\xe9\xff");
+    assert!(latin1_re(b"\xc3\xa9\xc3\xa9\xc3\xbf\xc3\xa9\x7fELF").is_none());
+
+    let captures = utf8_bytes_re(b"\xc3\xa9\xff\xc3\xa9").unwrap();
+    assert_eq!(captures[1].as_bytes(), "é".as_bytes());
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! { bytes utf8 utf8_word_re = ^ (['a'-'z' 'é']+) (.) (.) END }
+
+#[test]
+fn bytes_utf8() {
+    let captures = utf8_word_re(b"caf\xc3\xa9\xff\xe2\x82\xac").unwrap();
+    assert_eq!(captures.iter().map(|c| c.as_bytes()).collect::<Vec<_>>(),
+               vec![&b"caf\xc3\xa9\xff\xe2\x82\xac"[..], b"caf\xc3\xa9", b"\xff", b"\xe2\x82\xac"]);
+    assert!(utf8_word_re(b"\xc3\xa9\xff").is_none());
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! { stream error_stream_re = "ERROR " ([:digit:]+) }
+
+#[test]
+fn stream_across_chunks() {
+    use stream::{ChunkReader, StreamCapture};
+
+    let chunks = vec!["ok\nERR", "OR 4", "2\nok\nERROR 7", "", "\nERROR"];
+    let matches: Vec<_> = error_stream_re(ChunkReader::new(chunks))
+        .map(|m| m.unwrap())
+        .collect();
+    assert_eq!(matches, vec![
+        vec![StreamCapture { start: 3, end: 11, bytes: b"ERROR 42".to_vec() },
+             StreamCapture { start: 9, end: 11, bytes: b"42".to_vec() }],
+        vec![StreamCapture { start: 15, end: 22, bytes: b"ERROR 7".to_vec() },
+             StreamCapture { start: 21, end: 22, bytes: b"7".to_vec() }],
+        ]);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! { stream header_stream_re = ^ ("a"+) "b"? }
+
+#[test]
+fn stream_anchored() {
+    use std::io::BufReader;
+
+    // A one-byte buffer forces every step of the match to read more.
+    let reader = BufReader::with_capacity(1, &b"aaab aab"[..]);
+    let matches: Vec<_> = header_stream_re(reader).map(|m| m.unwrap()).collect();
+    assert_eq!(matches.len(), 1);
+    assert_eq!((matches[0][0].start, matches[0][0].end), (0, 4));
+    assert_eq!(matches[0][1].bytes, b"aaa");
+
+    assert_eq!(header_stream_re(&b"baaa"[..]).count(), 0);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! {
+    stream utf8 cafe_utf8_stream_re = "caf" ("é") [:alpha:];
+    stream cafe_stream_re = "caf" ("é") [:alpha:];
+}
+
+#[test]
+fn stream_utf8() {
+    use stream::ChunkReader;
+
+    // "é" is split between chunks, and matches as one character.
+    let chunks = vec![&b"un caf\xC3"[..], b"\xA9s caf\xC3", b"\xA9"];
+    let matches: Vec<_> = cafe_utf8_stream_re(ChunkReader::new(chunks.clone()))
+        .map(|m| m.unwrap())
+        .collect();
+    assert_eq!(matches.len(), 1);
+    assert_eq!((matches[0][0].start, matches[0][0].end), (3, 9));
+    assert_eq!(matches[0][1].bytes, "é".as_bytes());
+
+    // Decoded as Latin-1, "é" is the byte 0xE9.
+    assert_eq!(cafe_stream_re(ChunkReader::new(chunks)).count(), 0);
+    let matches: Vec<_> = cafe_stream_re(&b"caf\xE9s"[..]).map(|m| m.unwrap()).collect();
+    assert_eq!(matches[0][1].bytes, b"\xE9");
+
+    // A character cut off by the end of the stream is invalid.
+    assert_eq!(cafe_utf8_stream_re(&b"caf\xC3"[..]).count(), 0);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! { suffix extension_re = "." ([:alpha:]+) }
+
+#[test]
+fn suffix() {
+    assert_eq!(to_captures(extension_re("archive.tar.gz").unwrap()), vec![".gz", "gz"]);
+    assert_eq!(to_captures(extension_re("naïve.päge").unwrap()), vec![".päge", "päge"]);
+    assert!(extension_re("archive.tar.gz/").is_none());
+    assert!(extension_re("README").is_none());
+}
+
+#[test]
+fn reverse_leftmost_start() {
+    use reverse::{ends_with, leftmost_start};
+    use util::{Choice, Literal, PlusMax, PlusMin, Question, StarMax, Unanchored, YesChoice};
+    use util::named_choices::digit;
+
+    let number = (PlusMin(Choice(digit)), Question(Literal("%")));
+    assert_eq!(leftmost_start(&number, "up 250% today", 7), Some(3));
+    assert_eq!(leftmost_start(&number, "up 250% today", 6), Some(3));
+    assert_eq!(leftmost_start(&number, "up 250% today", 2), None);
+
+    assert_eq!(ends_with(&(Literal("b"), StarMax(Literal("ab"))), "aabab"), Some(2));
+    assert_eq!(ends_with(&Unanchored(Literal("b")), "xyzb"), Some(0));
+    assert_eq!(ends_with(&(Choice(YesChoice), Literal("b")), &b"\xffb"[..]), Some(0));
+
+    // Exponentially many ways to match, unless repetitions skip the
+    // positions they have been tried at.
+    let text = format!("b{}", "a".repeat(200));
+    assert_eq!(ends_with(&PlusMax(PlusMax(Literal("a"))), &text[..]), Some(1));
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn regex_syntax() {
+    let uri = regex!("([a-zA-Z][a-zA-Z0-9]*)://([^ /]+)(/[^ ]*)?");
+    assert_eq!(to_captures(uri("The url is http://foo/bar/baz so go click on it!").unwrap()),
+               vec!["http://foo/bar/baz", "http", "foo", "/bar/baz"]);
+
+    let version = regex!(r"^v?(\d+)\.(\d+)(?:\.(\d+))?$");
+    assert_eq!(to_captures(version("v1.22.3").unwrap()), vec!["v1.22.3", "1", "22", "3"]);
+    assert_eq!(to_captures(version("1.0").unwrap()), vec!["1.0", "1", "0"]);
+    assert!(version("1.0.").is_none());
+    assert!(version("x1.0").is_none());
+}
+
+#[test]
+fn regex_syntax_alternation_and_counts() {
+    let level = regex!("(?:ERROR|WARN(?:ING)?): ([^\\n]+)");
+    assert_eq!(to_captures(level("12:00 WARNING: low disk\nok").unwrap()),
+               vec!["WARNING: low disk", "low disk"]);
+
+    let hex = regex!("^#([[:digit:]a-fA-F]{2}){3}$");
+    assert!(hex("#ff80C0").is_some());
+    assert!(hex("#ff80C").is_none());
+    assert!(hex("#ff80C0f").is_none());
+
+    let lazy = regex!(r"^<(.+?)>\s*");
+    assert_eq!(to_captures(lazy("<a> <b>").unwrap()), vec!["<a> ", "a"]);
+
+    let class = regex!(r"^[\w\-.]{1,3}[^]\d]?$");
+    assert!(class("a-.").is_some());
+    assert!(class("a-.x").is_some());
+    assert!(class("a-.]").is_none());
+    assert!(class("a-.7").is_none());
+
+    // `^` anchors only its own alternative.
+    let command = regex!("^get|put");
+    assert!(command("get x").is_some());
+    assert_eq!(to_captures(command("x put").unwrap()), vec!["put"]);
+    assert!(command("x get").is_none());
+
+    let lazy_optional = regex!(r"^(a??)(a*)$");
+    assert_eq!(to_captures(lazy_optional("aa").unwrap()), vec!["aa", "", "aa"]);
+    let lazy_counted = Regex::new(r"^(a{1,3}?)(a*)$").unwrap();
+    assert_eq!(to_captures(lazy_counted.captures("aaa").unwrap()), vec!["aaa", "a", "aa"]);
+
+    // A counted group is still one group, reporting its last iteration.
+    let counted = regex!("(ab){2}");
+    assert_eq!(to_captures(counted("abab").unwrap()), vec!["abab", "ab"]);
+    let counted = Regex::new("(a|b){2,}(b)").unwrap();
+    assert_eq!(to_captures(counted.captures("abab").unwrap()), vec!["abab", "a", "b"]);
+    let counted = Regex::new("(ab){2}").unwrap();
+    assert_eq!(to_captures(counted.captures("abab").unwrap()), vec!["abab", "ab"]);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+#[test]
+fn runtime_regex() {
+    let pattern = String::from("([a-zA-Z][a-zA-Z0-9]*)://([^ /]+)(/[^ ]*)?");
+    let uri = Regex::new(&pattern).unwrap();
+    let text = "The url is http://foo/bar/baz so go click on it!";
+    assert_eq!(to_captures(uri.captures(text).unwrap()),
+               to_captures(regex!("([a-zA-Z][a-zA-Z0-9]*)://([^ /]+)(/[^ ]*)?")(text).unwrap()));
+    assert_eq!(uri.find(text).unwrap().to_str(), "http://foo/bar/baz");
+    assert!(!uri.is_match("no urls here"));
+
+    let hex: Regex = r"^#(?:[[:digit:]a-fA-F]{2}){3}$".parse().unwrap();
+    assert!(hex.is_match("#ff80C0"));
+    assert!(!hex.is_match("#ff80C"));
+    assert!(!hex.is_match("#ff80C0f"));
+}
+
+#[test]
+fn runtime_regex_error() {
+    let error = Regex::new("ab[c-a]").unwrap_err();
+    assert_eq!(error.offset, 3);
+    assert_eq!(error.to_string(), "invalid regex at offset 3: reversed range `c-a`");
+
+    let error = Regex::new("x a{0,200000}").unwrap_err();
+    assert_eq!(error.offset, 3);
+    assert_eq!(error.to_string(), "invalid regex at offset 3: counted repetition exceeds 1000");
+    assert!(Regex::new("a{0,4000000000}").is_err());
+    assert!(Regex::new("a{1000}").is_ok());
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! { route_user_re = ^ "/users/" ([:digit:]+) END }
+
+#[test]
+fn dyn_regex_routing_table() {
+    use dynamic::{DynRegex, MatchFn};
+    use util::{Accept, CaptureRe, End, Literal};
+
+    let routes: Vec<(&str, Box<dyn DynRegex>)> = vec![
+        ("user", Box::new(route_user_re as MatchFn)),
+        ("post", Box::new(Regex::new(r"^/posts/([\w-]+)$").unwrap())),
+        ("home", Box::new(CaptureRe((Literal("/"), (End, Accept))))),
+    ];
+    let route = |path| {
+        routes.iter().filter_map(|&(name, ref regex)| {
+            let mut captures = vec![];
+            regex.match_dyn(path, 0, &mut captures, &Accept).map(|_| (name, to_captures(captures)))
+        }).next()
+    };
+    assert_eq!(route("/users/42"), Some(("user", vec!["/users/42", "42"])));
+    assert_eq!(route("/posts/hello-world"), Some(("post", vec!["/posts/hello-world", "hello-world"])));
+    assert_eq!(route("/"), Some(("home", vec!["/"])));
+    assert_eq!(route("/users/x"), None);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! { struct Number = ([:digit:]+) ("." [:digit:]+)? }
+rusty_regex! { struct Greeting = ^ "hello" }
+
+fn count_matches<M: Matcher>(matcher: &M, text: &str) -> usize {
+    matcher.find_iter(text).count()
+}
+
+#[test]
+fn regex_struct() {
+    assert!(Number.is_match("pi is 3.14"));
+    assert_eq!(Number.find("pi is 3.14").unwrap().to_str(), "3.14");
+    assert_eq!(to_captures(Number.captures("x 12.5").unwrap()), vec!["12.5", "12", ".5"]);
+    assert_eq!(Number.find_iter("1, 22 and 3.5").map(|c| c.to_str()).collect::<Vec<_>>(),
+               vec!["1", "22", "3.5"]);
+
+    assert!(Greeting.is_match("hello world"));
+    assert!(!Greeting.is_match("oh hello"));
+    assert_eq!(count_matches(&Greeting, "hellohello"), 1);
+    assert_eq!(count_matches(&Number, "1 2 3"), 3);
+    assert_eq!(count_matches(&Regex::new("a*").unwrap(), "baaé"), 4);
+}
+
+rusty_regex! { struct Motif = "a" ['a' 'g']+ }
+
+#[test]
+fn find_overlapping() {
+    let spans = |found: Vec<Capture>| -> Vec<(usize, usize)> {
+        found.iter().map(|c| (c.start, c.end)).collect()
+    };
+    let aa = Regex::new("aa").unwrap();
+    assert_eq!(spans(aa.find_overlapping_iter("aaaa").collect()), vec![(0, 2), (1, 3), (2, 4)]);
+    assert_eq!(spans(Motif.find_overlapping_iter("tagag").collect()), vec![(1, 5), (3, 5)]);
+    assert_eq!(spans(Motif.find_overlapping_iter("tagag").all_ends().collect()),
+               vec![(1, 3), (1, 4), (1, 5), (3, 5)]);
+    assert_eq!(spans(Regex::new("^a+").unwrap().find_overlapping_iter("aab").all_ends().collect()),
+               vec![(0, 1), (0, 2)]);
+    assert_eq!(Motif.find_overlapping_iter("ttt").count(), 0);
+}
+
+rusty_regex! { struct Nested = ("a"+)+ }
+
+#[test]
+fn all_ends_nested_repetitions() {
+    // Without memoizing, each start would try the 2^63 ways of
+    // splitting the rest of the text into iterations.
+    let text = "a".repeat(64);
+    assert_eq!(Nested.ends_at(&text, 0), (1..65).collect::<Vec<_>>());
+    assert_eq!(Nested.find_overlapping_iter(&text).all_ends().count(), 64 * 65 / 2);
+    let nested = Regex::new("(a+)+").unwrap();
+    assert_eq!(nested.find_overlapping_iter(&text).all_ends().count(), 64 * 65 / 2);
+}
+
+rusty_regex! { buffered setting_re = ^ ([:alpha:]+) "=" ([:digit:]+)? }
+
+#[test]
+fn captures_into() {
+    let mut captures = CaptureBuf::with_capacity(3);
+    for (line, expected) in [("a=1", vec!["a=1", "a", "1"]), ("-", vec![]), ("b=", vec!["b=", "b"])] {
+        assert_eq!(setting_re(line, &mut captures), !expected.is_empty());
+        assert_eq!(captures.iter().map(|c| c.to_str()).collect::<Vec<_>>(), expected);
+    }
+
+    let text = String::from("x 1.5");
+    let mut captures = captures.recycle();
+    assert!(Number.captures_into(&text, &mut captures));
+    assert_eq!(captures[0].to_str(), "1.5");
+    assert!(Regex::new("[0-9]+").unwrap().captures_into(&text, &mut captures));
+    assert_eq!(captures.len(), 1);
+}
+
+#[test]
+fn capture_array() {
+    let text = "abcdef";
+    let capture = |start| Capture::new(text, start, start + 1);
+    let mut array: CaptureArray = (0..5).map(capture).collect();
+    let drained: Vec<_> = array.drain(1..3).collect();
+    assert_eq!(drained, [capture(1), capture(2)]);
+    assert_eq!(&array[..], [capture(0), capture(3), capture(4)]);
+    array.splice(1..1, drained);
+    array[4].end = 6;
+    assert_eq!(to_captures(array.into_iter().collect()), vec!["a", "b", "c", "d", "ef"]);
+}
+
+#[test]
+fn regex_struct_composes() {
+    use matcher::captures_at;
+    use util::{Accept, CaptureRe, End, Literal};
+
+    let version = CaptureRe((Literal("v"), (Number, (Literal("-"), (Number, (End, Accept))))));
+    assert_eq!(to_captures(captures_at(&version, "v1.2-3", 0).unwrap()),
+               vec!["v1.2-3", "1.2", "1", ".2", "3", "3"]);
+    assert!(captures_at(&version, "v1.2-", 0).is_none());
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! {
+    /// A key and its value.
+    key_value_re = ^ ([:alpha:]+) "=" ([^ ' ']*);
+    #[inline]
+    pub(crate) comment_re = ^ "#";
+    #[cfg(test)]
+    #[allow(dead_code)]
+    struct Blank = ^ [:space:]* END;
+    #[cfg(not(test))]
+    struct Blank = ^ "never"
+}
+
+mod visibility {
+    rusty_regex! {
+        word_re = ^ [:alpha:]+;
+        pub(self) digits_re = ^ [:digit:]+
+    }
+
+    pub fn is_digits(text: &str) -> bool {
+        digits_re(text).is_some()
+    }
+}
+
+#[test]
+fn several_definitions() {
+    assert_eq!(to_captures(key_value_re("name=value rest").unwrap()),
+               vec!["name=value", "name", "value"]);
+    assert!(comment_re("# note").is_some());
+    assert!(Blank.is_match("  \t"));
+    assert!(!Blank.is_match(" x "));
+
+    // Without a visibility, a definition is `pub`.
+    assert!(visibility::word_re("abc").is_some());
+    assert!(visibility::is_digits("123"));
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+const SEPARATOR: &str = "://";
+
+rusty_regex! {
+    fragment ident = [:alpha: '_'] [:alpha: :digit: '_']*;
+    fragment octet = [:digit:] [:digit:]? [:digit:]?;
+    assignment_re = ^ (ident) [:space:]* "=" [:space:]* (ident) END;
+    ipv4_re = (octet "." octet "." octet "." octet);
+    scheme_re = ^ (ident) SEPARATOR;
+    bytes ident_bytes_re = ^ ident
+}
+
+#[test]
+fn fragments() {
+    assert_eq!(to_captures(assignment_re("foo_1 = bar").unwrap()), vec!["foo_1 = bar", "foo_1", "bar"]);
+    assert!(assignment_re("1foo = bar").is_none());
+    assert_eq!(to_captures(ipv4_re("host 10.0.255.1:80").unwrap()), vec!["10.0.255.1", "10.0.255.1"]);
+    assert_eq!(to_captures(scheme_re("http://x").unwrap()), vec!["http://", "http"]);
+    assert_eq!(ident_bytes_re(b"abc def").unwrap()[0].as_bytes(), b"abc");
+    assert_eq!(RegexThen::<str>::literals(&(ident, util::Literal("x"))).required, Some("x"));
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+mod base58 {
+    use CharRange;
+
+    pub struct Char;
+
+    impl CharRange for Char {
+        fn test(&self, c: char) -> bool {
+            c.is_ascii_alphanumeric() && !"0OIl".contains(c)
+        }
+
+        fn may_match_non_ascii(&self) -> bool {
+            false
+        }
+    }
+}
+
+rusty_regex! {
+    address_re = ^ "1" [:self::base58::Char:]+ END;
+    hash_re = ^ ({util::PlusMax(util::Choice(util::RangeChoice('0', '9')))}) ":" {Number} END
+}
+
+#[test]
+fn user_types() {
+    assert!(address_re("1BoatSLRHtKNngkdXEeobR76b53LETtpyT").is_some());
+    assert!(address_re("1BoatSLRHtKNngkdXEeobR76b53LETtpy0").is_none());
+    assert_eq!(to_captures(hash_re("12:3.5").unwrap()), vec!["12:3.5", "12", "3.5", "3", ".5"]);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+fn is_octet(text: &str) -> bool {
+    text.parse::<u8>().is_ok()
+}
+
+rusty_regex! {
+    fragment octet_0_255 = (if is_octet => [:digit:]+);
+    ipv4_checked_re = ^ octet_0_255 "." octet_0_255 "." octet_0_255 "." octet_0_255 END;
+    month_re = (if |name| ["jan", "feb", "mar"].contains(&name) => [:alpha:]+)
+}
+
+#[test]
+fn predicates() {
+    assert!(ipv4_checked_re("10.0.255.1").is_some());
+    assert!(ipv4_checked_re("10.0.256.1").is_none());
+    // The predicate rejects "feb" + "x" and backtracks within the
+    // greedy repetition until it finds no match starting there.
+    assert_eq!(to_captures(month_re("febx feb").unwrap()), vec!["feb", "feb"]);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! {
+    pub host_port_re -> HostPort = ^ (host: String = [^ ':']+) (?: ":" (port: u16 = [:digit:]+))? END;
+    pub(crate) user_re -> User = ^ (name: String = [:alpha:]+) (id: Option<u32> = [:digit:]+)?
+                                   (?: " " (tag: String = [:alpha:]+))* END
+}
+
+#[test]
+fn typed_captures() {
+    assert_eq!(host_port_re("example.com:8080").unwrap().unwrap(),
+               HostPort { host: "example.com".to_string(), port: Some(8080) });
+    assert_eq!(host_port_re("example.com").unwrap().unwrap(),
+               HostPort { host: "example.com".to_string(), port: None });
+    assert!(host_port_re("example.com:").is_none());
+
+    let error = host_port_re("example.com:99999").unwrap().unwrap_err();
+    assert_eq!(error.field, "port");
+    assert_eq!(error.to_string(),
+               "field `port`: cannot parse \"99999\": number too large to fit in target type");
+
+    assert_eq!(user_re("ann42 admin ops").unwrap().unwrap(),
+               User { name: "ann".to_string(), id: Some(42), tag: Some("ops".to_string()) });
+    assert_eq!(user_re("bob").unwrap().unwrap(),
+               User { name: "bob".to_string(), id: None, tag: None });
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+#[derive(Debug, PartialEq, FromCaptures)]
+struct LogLine {
+    level: String,
+    code: Option<u32>,
+    tag: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, FromCaptures)]
+struct LogLevel {
+    level: String,
+    code: Option<u32>,
+}
+
+rusty_regex! {
+    log_level_re = ^ (level = [:alpha:]+) (?: " #" (code = [:digit:]+))?;
+    history log_line_re = ^ (level = [:alpha:]+) (?: " #" (code = [:digit:]+))? (?: " [" (tag = [^ ']']+) "]")* END
+}
+
+#[test]
+fn derive_from_captures() {
+    let line = log_level_re("WARN #42 [disk]").unwrap();
+    assert_eq!(line[1].name(), Some("level"));
+    assert_eq!(LogLevel::from_captures(&line).unwrap(),
+               LogLevel { level: "WARN".to_string(), code: Some(42) });
+    assert_eq!(LogLevel::from_captures(&log_level_re("INFO").unwrap()).unwrap(),
+               LogLevel { level: "INFO".to_string(), code: None });
+
+    // `Vec` fields need every iteration, so only `from_history` fills them.
+    let line = log_line_re("WARN #42 [disk] [io]").unwrap();
+    assert_eq!(line[1][0].name(), Some("level"));
+    assert_eq!(LogLine::from_history(&line).unwrap(), LogLine {
+        level: "WARN".to_string(),
+        code: Some(42),
+        tag: vec!["disk".to_string(), "io".to_string()],
+    });
+
+    let line = log_line_re("INFO").unwrap();
+    assert_eq!(LogLine::from_history(&line).unwrap(),
+               LogLine { level: "INFO".to_string(), code: None, tag: vec![] });
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! {
+    list_re = ^ ([:digit:]+) ("," ([:digit:]+))* END;
+    lazy_list_re = ^ (['a' 'b'])*? ("b"+) END;
+    history list_history_re = ^ ([:digit:]+) ("," ([:digit:]+))* END;
+    nested_list_re = ^ (([:digit:])+ ",")* END;
+    pairs_re = ^ ("a" [:digit:])* "b"
+}
+
+#[test]
+fn repeated_groups_report_last_iteration() {
+    assert_eq!(to_captures(list_re("1,22,333").unwrap()), vec!["1,22,333", "1", ",333", "333"]);
+    assert_eq!(to_captures(list_re("1").unwrap()), vec!["1", "1"]);
+    assert_eq!(to_captures(lazy_list_re("abab").unwrap()), vec!["abab", "a", "b"]);
+
+    let regex = Regex::new("^(?:(a)|(b))+$").unwrap();
+    assert_eq!(to_captures(regex.captures("abab").unwrap()), vec!["abab", "b"]);
+}
+
+#[test]
+fn repeated_groups_nested() {
+    assert_eq!(to_captures(nested_list_re("12,345,6,").unwrap()), vec!["12,345,6,", "6,", "6"]);
+    assert_eq!(to_captures(nested_list_re("12,").unwrap()), vec!["12,", "12,", "2"]);
+    assert_eq!(to_captures(nested_list_re("").unwrap()), vec![""]);
+}
+
+#[test]
+fn failed_repetition_exits_stay_linear() {
+    // Each of the 100000 ways of leaving the repetition fails. Were the
+    // captures of the iterations before it moved every time, as many
+    // as there are, this would take minutes rather than milliseconds.
+    // Every iteration recurses, hence the stack.
+    let text = "a0".repeat(100_000) + "c";
+    let matcher = std::thread::Builder::new().stack_size(1 << 30);
+    let result = matcher.spawn(move || pairs_re(&text).is_none()).unwrap().join().unwrap();
+    assert!(result);
+    assert_eq!(to_captures(pairs_re("a0a1b").unwrap()), vec!["a0a1b", "a1"]);
+}
+
+#[test]
+fn history() {
+    let list = list_history_re("1,22,333").unwrap();
+    let spans: Vec<Vec<&str>> = list.into_iter().map(to_captures).collect();
+    assert_eq!(spans, vec![vec!["1,22,333"], vec!["1"], vec![",22", ",333"], vec!["22", "333"]]);
+    assert_eq!(list_history_re("1").unwrap().len(), 2);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! {
+    keyword_re = ({util::Alt(util::Literal("in"), util::Literal("int"))}) ("eger")?;
+    posix posix_keyword_re = ({util::Alt(util::Literal("in"), util::Literal("int"))}) ("eger")?;
+    posix posix_digits_re = ^ ([:digit:]+?) ([:digit:]*);
+    posix posix_optional_re = ^ ("a")? ("a"*);
+}
+
+#[test]
+fn posix() {
+    assert_eq!(to_captures(keyword_re("an integer").unwrap()), vec!["in", "in"]);
+    assert_eq!(to_captures(posix_keyword_re("an integer").unwrap()), vec!["integer", "int", "eger"]);
+    assert_eq!(to_captures(posix_keyword_re("x in").unwrap()), vec!["in", "in"]);
+    assert_eq!(posix_keyword_re("none"), None);
+
+    // Each group, from the left, matches as much as it can.
+    assert_eq!(to_captures(posix_digits_re("123").unwrap()), vec!["123", "123", ""]);
+    // A group that takes part beats one left out, even for a longer
+    // capture of the group after it.
+    assert_eq!(to_captures(posix_optional_re("aaa").unwrap()), vec!["aaa", "a", "aa"]);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex! {
+    is_match has_port = ^ (host = [^ ':']+) (?: ":" (port: u16 = [:digit:]+))? END;
+    is_match has_date = (if |name| ["jan", "feb"].contains(&name) => [:alpha:]+) " " ([:digit:]+)
+}
+
+#[test]
+fn is_match() {
+    assert!(has_port("example.com:80"));
+    assert!(has_port("example.com"));
+    assert!(!has_port("example.com:http"));
+
+    // Predicates still apply once their group no longer captures.
+    assert!(has_date("due feb 12"));
+    assert!(!has_date("due dec 12"));
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+const VERSION: &str = "1.20.3";
+
+const SLASH: char = '/';
+
+rusty_regex! {
+    const version_re = ^ [:digit:]+ ("." [:digit:]+)* END;
+    const path_re = ("/" [^:space: SLASH]+)+ "/"? END;
+    const greek_re = ['α'-'ω']+ "!"
+}
+
+const _: () = assert!(version_re(VERSION));
+
+#[test]
+fn const_match() {
+    assert!(!version_re("1.20.") && !version_re("v1.2"));
+    assert!(path_re("see /usr/local/") && !path_re("/usr/local "));
+    assert!(greek_re("λόγος!") && !greek_re("logos!"));
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_regex_set! {
+    log_kind = {
+        ^ "ERROR" [:space:]*;
+        "timeout" (" after " [:digit:]+ "ms")?;
+        [:digit:]+ "%";
+        ^ ([:alpha:]+ ":")?;
+        "disk" [:space:]+ "full" END
+    }
+}
+
+#[test]
+fn regex_set() {
+    assert_eq!(log_kind("ERROR disk  full"), vec![0, 3, 4]);
+    assert_eq!(log_kind("WARN: timeout after 30ms at 95%"), vec![1, 2, 3]);
+    assert_eq!(log_kind(""), vec![3]);
+    assert_eq!(log_kind("disk full!"), vec![3]);
+}
+
+///////////////////////////////////////////////////////////////////////////
+
+rusty_lexer! {
+    enum Token {
+        Let = "let";
+        Ident = [:alpha: '_'] [:alpha: :digit: '_']*;
+        Number = [:digit:]+ ("." [:digit:]+)?;
+        Arrow = "->";
+        Minus = "-";
+        Space = [:space:]+
+    }
+}
+
+#[test]
+fn lexer() {
+    let tokens: Vec<_> = Token::lex("let x->1.5 - lettuce").map(Result::unwrap).collect();
+    assert_eq!(tokens, vec![(Token::Let, "let", 0..3),
+                            (Token::Space, " ", 3..4),
+                            (Token::Ident, "x", 4..5),
+                            (Token::Arrow, "->", 5..7),
+                            (Token::Number, "1.5", 7..10),
+                            (Token::Space, " ", 10..11),
+                            (Token::Minus, "-", 11..12),
+                            (Token::Space, " ", 12..13),
+                            (Token::Ident, "lettuce", 13..20)]);
+
+    let tokens: Vec<_> = Token::lex("a?#1").collect();
+    assert_eq!(tokens[1], Err(lexer::LexError { position: 1 }));
+    assert_eq!(tokens[2], Err(lexer::LexError { position: 2 }));
+    assert_eq!(tokens[3], Ok((Token::Number, "1", 3..4)));
+    assert_eq!(tokens[1].clone().unwrap_err().to_string(), "unrecognized input at byte 1");
+}
@@ -0,0 +1,210 @@
+use core::char;
+use core::cmp;
+use core::fmt;
+use core::str;
+
+use prefilter::FirstSet;
+
+/// Text that a regex can be matched against. Positions are always byte
+/// offsets; what differs is how a character is decoded at a position.
+pub trait Text {
+    fn as_bytes(&self) -> &[u8];
+
+    /// Decodes the character starting at `position`, returning it along
+    /// with its length in bytes, or `None` at the end of the text.
+    fn char_at(&self, position: usize) -> Option<(char, usize)>;
+
+    /// Decodes the character ending at `position`, returning it along
+    /// with its length in bytes, or `None` at the start of the text.
+    fn char_before(&self, position: usize) -> Option<(char, usize)>;
+
+    /// Whether `literal` appears starting at `position`, encoded the
+    /// way this text decodes characters.
+    fn has_literal_at(&self, position: usize, literal: &str) -> bool {
+        self.as_bytes()[position..].starts_with(literal.as_bytes())
+    }
+
+    /// Whether `literal` appears ending at `position`.
+    fn has_literal_before(&self, position: usize, literal: &str) -> bool {
+        self.as_bytes()[..position].ends_with(literal.as_bytes())
+    }
+
+    /// How many bytes `literal` takes up in this text.
+    fn literal_len(literal: &str) -> usize {
+        literal.len()
+    }
+
+    /// The bytes `literal` is encoded as in this text, for scanning, or
+    /// `None` if they cannot be borrowed from it.
+    fn literal_bytes(literal: &'static str) -> Option<&'static [u8]> {
+        Some(literal.as_bytes())
+    }
+
+    /// Whether `position` is the end of the text.
+    fn is_end(&self, position: usize) -> bool {
+        position == self.as_bytes().len()
+    }
+
+    /// Which bytes may begin a character in `first_set`.
+    fn start_table(first_set: &FirstSet) -> [bool; 256];
+}
+
+impl Text for str {
+    fn as_bytes(&self) -> &[u8] {
+        str::as_bytes(self)
+    }
+
+    fn char_at(&self, position: usize) -> Option<(char, usize)> {
+        self[position..].chars().next().map(|c| (c, c.len_utf8()))
+    }
+
+    fn char_before(&self, position: usize) -> Option<(char, usize)> {
+        self[..position].chars().next_back().map(|c| (c, c.len_utf8()))
+    }
+
+    fn start_table(first_set: &FirstSet) -> [bool; 256] {
+        first_set.utf8_table()
+    }
+}
+
+/// Raw bytes: every byte is one character, with bytes above `0x7F`
+/// read as the Latin-1 character of the same value.
+impl Text for [u8] {
+    fn as_bytes(&self) -> &[u8] {
+        self
+    }
+
+    fn char_at(&self, position: usize) -> Option<(char, usize)> {
+        self.get(position).map(|&b| (b as char, 1))
+    }
+
+    fn char_before(&self, position: usize) -> Option<(char, usize)> {
+        self[..position].last().map(|&b| (b as char, 1))
+    }
+
+    // Literals are matched a character to a byte, just as classes and
+    // `.` are, so `"é"` matches the byte `0xE9`.
+    fn has_literal_at(&self, position: usize, literal: &str) -> bool {
+        let mut rest = self[position..].iter();
+        literal.chars().all(|c| rest.next().map(|&b| b as char) == Some(c))
+    }
+
+    fn has_literal_before(&self, position: usize, literal: &str) -> bool {
+        let mut rest = self[..position].iter().rev();
+        literal.chars().rev().all(|c| rest.next().map(|&b| b as char) == Some(c))
+    }
+
+    fn literal_len(literal: &str) -> usize {
+        latin1_len(literal)
+    }
+
+    fn literal_bytes(literal: &'static str) -> Option<&'static [u8]> {
+        latin1_bytes(literal)
+    }
+
+    fn start_table(first_set: &FirstSet) -> [bool; 256] {
+        first_set.byte_table()
+    }
+}
+
+/// Text that is bytes rather than characters, which byte strings such
+/// as `b"\xFF"` can be matched against.
+#[diagnostic::on_unimplemented(
+    message = "byte strings cannot be matched against `{Self}`",
+    label = "only `bytes` definitions can match byte strings")]
+pub trait ByteText: Text {
+    /// Whether `bytes` appear starting at `position`.
+    fn has_bytes_at(&self, position: usize, bytes: &[u8]) -> bool {
+        self.as_bytes()[position..].starts_with(bytes)
+    }
+
+    /// Whether `bytes` appear ending at `position`.
+    fn has_bytes_before(&self, position: usize, bytes: &[u8]) -> bool {
+        self.as_bytes()[..position].ends_with(bytes)
+    }
+}
+
+impl ByteText for [u8] { }
+
+impl ByteText for Utf8Bytes { }
+
+/// The length of `literal` with each character a byte, as `[u8]` text
+/// decodes it.
+pub fn latin1_len(literal: &str) -> usize {
+    literal.chars().count()
+}
+
+/// `literal` with each character a byte, if that is how it is encoded
+/// in UTF-8 too, i.e., it is ASCII.
+pub fn latin1_bytes(literal: &'static str) -> Option<&'static [u8]> {
+    Some(literal.as_bytes()).filter(|_| literal.is_ascii())
+}
+
+/// Bytes that are mostly UTF-8. Valid sequences decode to their
+/// character; each byte of an invalid sequence decodes on its own to
+/// `U+FFFD REPLACEMENT CHARACTER`.
+#[derive(PartialEq, Eq)]
+#[repr(transparent)]
+pub struct Utf8Bytes([u8]);
+
+impl Utf8Bytes {
+    pub fn new(bytes: &[u8]) -> &Utf8Bytes {
+        // Safe because `Utf8Bytes` is a transparent wrapper around `[u8]`.
+        unsafe { &*(bytes as *const [u8] as *const Utf8Bytes) }
+    }
+}
+
+impl fmt::Debug for Utf8Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // As `String::from_utf8_lossy` would show it.
+        f.write_str("\"")?;
+        for chunk in self.0.utf8_chunks() {
+            write!(f, "{}", chunk.valid().escape_debug())?;
+            if !chunk.invalid().is_empty() {
+                f.write_str("\u{fffd}")?;
+            }
+        }
+        f.write_str("\"")
+    }
+}
+
+impl Text for Utf8Bytes {
+    fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn char_at(&self, position: usize) -> Option<(char, usize)> {
+        let bytes = &self.0[position..cmp::min(position + 4, self.0.len())];
+        let valid = match str::from_utf8(bytes) {
+            Ok(valid) => valid,
+            Err(error) => str::from_utf8(&bytes[..error.valid_up_to()]).unwrap(),
+        };
+        match valid.chars().next() {
+            Some(c) => Some((c, c.len_utf8())),
+            None if bytes.is_empty() => None,
+            None => Some((char::REPLACEMENT_CHARACTER, 1)),
+        }
+    }
+
+    fn char_before(&self, position: usize) -> Option<(char, usize)> {
+        // Prefer the longest valid sequence ending at `position`, so
+        // that we agree with how `char_at` decodes from the left.
+        for len in (1..cmp::min(position, 4) + 1).rev() {
+            match self.char_at(position - len) {
+                Some((c, l)) if l == len => return Some((c, len)),
+                _ => { }
+            }
+        }
+        if position == 0 {
+            None
+        } else {
+            Some((char::REPLACEMENT_CHARACTER, 1))
+        }
+    }
+
+    fn start_table(first_set: &FirstSet) -> [bool; 256] {
+        // Stray continuation bytes decode to `U+FFFD`, so unlike `str`
+        // any byte may begin a character.
+        first_set.byte_table()
+    }
+}
@@ -0,0 +1,141 @@
+//! Support for typed groups, `(name: Type = ...)`, whose text is
+//! parsed with `FromStr` into a field of the struct returned by
+//! `rusty_regex! { name -> Struct = ... }`, and for
+//! `#[derive(FromCaptures)]`, which fills a struct from named groups.
+
+use alloc::boxed::Box;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::error::Error;
+use core::fmt;
+use core::str::FromStr;
+
+use Capture;
+
+/// A struct built from the named groups of a match. Derive it with
+/// `#[derive(FromCaptures)]`: each field is parsed with `FromStr` from
+/// the group of the same name. A field of type `Option<T>` is `None` if
+/// its group took no part in the match; any other field takes the
+/// group's last capture.
+///
+/// A repeated group is only captured once, for its last iteration, so a
+/// struct with a field of type `Vec<T>`, which collects every capture of
+/// its group, does not implement this trait, only `FromHistory`:
+///
+#[cfg_attr(feature = "alloc", doc = "```compile_fail")]
+#[cfg_attr(not(feature = "alloc"), doc = "```ignore")]
+/// #[macro_use] extern crate rusty_regex;
+/// use rusty_regex::FromCaptures;
+///
+/// #[derive(FromCaptures)]
+/// struct Tags { tag: Vec<String> }
+///
+/// rusty_regex! { tags_re = (?: "#" (tag = [:alpha:]+))* }
+/// # fn main() {
+/// Tags::from_captures(&tags_re("#a#b").unwrap()); // would see only `b`
+/// # }
+/// ```
+pub trait FromCaptures: Sized {
+    fn from_captures(captures: &[Capture]) -> Result<Self, FieldError>;
+}
+
+/// A struct built from what a `rusty_regex! { history ... }` definition
+/// returns, every iteration of every group. `#[derive(FromCaptures)]`
+/// implements it too, with each field of type `Vec<T>` collecting every
+/// capture of its group.
+pub trait FromHistory: Sized {
+    fn from_history(history: &[Vec<Capture>]) -> Result<Self, FieldError>;
+}
+
+/// Why a field of a typed match could not be produced.
+#[derive(Debug)]
+pub struct FieldError {
+    /// The name of the field.
+    pub field: &'static str,
+
+    /// The text its group matched, or `None` if the group took no part
+    /// in the match (e.g., it was inside a `?` that matched nothing).
+    pub text: Option<String>,
+
+    /// Why `FromStr` rejected `text`.
+    pub error: Option<Box<dyn Error + Send + Sync>>,
+}
+
+impl fmt::Display for FieldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (&self.text, &self.error) {
+            (Some(text), Some(error)) => {
+                write!(f, "field `{}`: cannot parse {:?}: {}", self.field, text, error)
+            }
+            _ => write!(f, "field `{}` did not take part in the match", self.field),
+        }
+    }
+}
+
+impl Error for FieldError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.error {
+            Some(ref error) => Some(&**error),
+            None => None,
+        }
+    }
+}
+
+/// Parses the text recorded in a field's slot.
+pub fn parse_field<F>(field: &'static str, text: &str, slot: Option<(usize, usize)>)
+                      -> Result<F, FieldError>
+    where F: FromStr, F::Err: Error + Send + Sync + 'static
+{
+    match slot {
+        Some((start, end)) => parse(field, &text[start..end]),
+        None => Err(FieldError { field, text: None, error: None }),
+    }
+}
+
+/// Like `parse_field`, but `None` if the group took no part in the
+/// match, for typed groups under `?` or `*`, or declared `Option<T>`.
+pub fn parse_optional_field<F>(field: &'static str, text: &str, slot: Option<(usize, usize)>)
+                               -> Result<Option<F>, FieldError>
+    where F: FromStr, F::Err: Error + Send + Sync + 'static
+{
+    slot.map(|(start, end)| parse(field, &text[start..end])).transpose()
+}
+
+/// The text of each capture of the group called `name`, in order.
+pub fn named<'c, 'text>(captures: &'c [Capture<'text>], name: &'static str)
+                        -> impl DoubleEndedIterator<Item = &'text str> + 'c {
+    captures.iter().filter(move |c| c.name() == Some(name)).map(|c| c.to_str())
+}
+
+/// Parses the last capture of the group called `field`.
+pub fn parse_named<F>(field: &'static str, captures: &[Capture]) -> Result<F, FieldError>
+    where F: FromStr, F::Err: Error + Send + Sync + 'static
+{
+    match named(captures, field).next_back() {
+        Some(text) => parse(field, text),
+        None => Err(FieldError { field, text: None, error: None }),
+    }
+}
+
+/// Like `parse_named`, but `None` if there is no such capture.
+pub fn parse_named_optional<F>(field: &'static str, captures: &[Capture])
+                               -> Result<Option<F>, FieldError>
+    where F: FromStr, F::Err: Error + Send + Sync + 'static
+{
+    named(captures, field).next_back().map(|text| parse(field, text)).transpose()
+}
+
+/// Parses every capture of the group called `field`.
+pub fn parse_named_all<F>(field: &'static str, captures: &[Capture]) -> Result<Vec<F>, FieldError>
+    where F: FromStr, F::Err: Error + Send + Sync + 'static
+{
+    named(captures, field).map(|text| parse(field, text)).collect()
+}
+
+fn parse<F>(field: &'static str, text: &str) -> Result<F, FieldError>
+    where F: FromStr, F::Err: Error + Send + Sync + 'static
+{
+    text.parse().map_err(|error| {
+        FieldError { field, text: Some(text.to_string()), error: Some(Box::new(error)) }
+    })
+}
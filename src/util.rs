@@ -1,331 +1,2119 @@
-use super::{CharRange, Capture, RegexThen, RegexContinuation};
+use core::cell::Cell;
+use core::ptr;
+
+#[cfg(feature = "alloc")]
+use alloc::vec::Vec;
+#[cfg(feature = "alloc")]
+use core::cell::RefCell;
+
+use super::{ByteText, CharRange, Capture, Captures, RegexThen, RegexContinuation, RegexRev,
+            RegexRevContinuation, Text};
+use captures;
+use prefilter::{Cache, FirstSet, Literals, Prefilter};
+
+/// What a match is run for, as told by `RegexContinuation::mode`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Only the last iteration of a repeated group is captured.
+    Captures,
+    /// Every iteration of a repeated group is captured; see `history`.
+    History,
+    /// Nothing is captured: only where matches end is wanted, each one
+    /// noted before failing to have the next tried; see
+    /// `matcher::ends_at`.
+    Ends,
+}
 
 #[derive(Clone, Debug)]
 pub struct Accept;
 
-impl RegexThen for Accept {
+impl<T> RegexThen<T> for Accept
+    where T: ?Sized + Text
+{
     fn match_then<'text,C>(&self,
-                           text: &'text str,
+                           text: &'text T,
                            position: usize,
-                           captures: &mut Vec<Capture<'text>>,
+                           captures: &mut Captures<'text, T>,
                            continuation: &C)
                            -> Option<usize>
-        where C: RegexContinuation
+        where C: RegexContinuation<T>
     {
         continuation.match_continue(text, position, captures)
     }
+
+    fn literals(&self) -> Literals {
+        Literals::zero_width()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        FirstSet::nullable()
+    }
+
+    fn may_capture(&self) -> bool {
+        false
+    }
+}
+
+impl<T> RegexRev<T> for Accept
+    where T: ?Sized + Text
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        continuation.match_rev_continue(text, position)
+    }
+}
+
+/// As a continuation, `Accept` ends the match successfully, so it is
+/// where the captures of a repetition's earlier iterations are finally
+/// dropped; see `LastIteration`.
+impl<T> RegexContinuation<T> for Accept
+    where T: ?Sized + Text
+{
+    fn match_continue<'text>(&self,
+                             _text: &'text T,
+                             position: usize,
+                             captures: &mut Captures<'text, T>)
+                             -> Option<usize>
+    {
+        drop_superseded(captures, 0);
+        Some(position)
+    }
+}
+
+impl<T> RegexRevContinuation<T> for Accept
+    where T: ?Sized + Text
+{
+    fn match_rev_continue(&self,
+                          _text: &T,
+                          position: usize)
+                          -> Option<usize>
+    {
+        Some(position)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct End;
+
+impl<T> RegexThen<T> for End
+    where T: ?Sized + Text
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        if text.is_end(position) {
+            continuation.match_continue(text, position, captures)
+        } else {
+            None
+        }
+    }
+
+    fn literals(&self) -> Literals {
+        Literals::zero_width()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        FirstSet::nullable()
+    }
+
+    fn may_capture(&self) -> bool {
+        false
+    }
+}
+
+impl<T> RegexRev<T> for End
+    where T: ?Sized + Text
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        if text.is_end(position) {
+            continuation.match_rev_continue(text, position)
+        } else {
+            None
+        }
+    }
+}
+
+/// Matches nothing, only at the start of the text; `^` anywhere but at
+/// the start of a `regex!` pattern.
+#[derive(Clone, Debug)]
+pub struct Start;
+
+impl<T> RegexThen<T> for Start
+    where T: ?Sized + Text
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        if position == 0 {
+            continuation.match_continue(text, position, captures)
+        } else {
+            None
+        }
+    }
+
+    fn literals(&self) -> Literals {
+        Literals::zero_width()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        FirstSet::nullable()
+    }
+
+    fn may_capture(&self) -> bool {
+        false
+    }
+}
+
+impl<T> RegexRev<T> for Start
+    where T: ?Sized + Text
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        if position == 0 {
+            continuation.match_rev_continue(text, position)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T,R,U> RegexThen<T> for (R,U)
+    where T: ?Sized + Text, R: RegexThen<T>, U: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        let state = SeqMidState { next: &self.1, continuation };
+        self.0.match_then(text, position, captures, &state)
+    }
+
+    fn literals(&self) -> Literals {
+        self.0.literals().then(self.1.literals())
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set().then(self.1.first_set())
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture() || self.1.may_capture()
+    }
+}
+
+impl<T,R,U> RegexRev<T> for (R,U)
+    where T: ?Sized + Text, R: RegexRev<T>, U: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        let state = SeqMidState { next: &self.0, continuation };
+        self.1.match_rev_then(text, position, &state)
+    }
+}
+
+struct SeqMidState<'r,R:'r,C:'r> {
+    next: &'r R,
+    continuation: &'r C,
+}
+
+impl<'r,T,R,C> RegexContinuation<T> for SeqMidState<'r,R,C>
+    where T: ?Sized + Text, R: RegexThen<T>, C: RegexContinuation<T>
+{
+    fn match_continue<'text>(&self,
+                             text: &'text T,
+                             position: usize,
+                             captures: &mut Captures<'text, T>)
+                             -> Option<usize>
+    {
+        self.next.match_then(text, position, captures, self.continuation)
+    }
+
+    fn mode(&self) -> Mode {
+        self.continuation.mode()
+    }
+}
+
+impl<'r,T,R,C> RegexRevContinuation<T> for SeqMidState<'r,R,C>
+    where T: ?Sized + Text, R: RegexRev<T>, C: RegexRevContinuation<T>
+{
+    fn match_rev_continue(&self,
+                          text: &T,
+                          position: usize)
+                          -> Option<usize>
+    {
+        self.next.match_rev_then(text, position, self.continuation)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StarMax<R>(pub R);
+
+impl<T,R> RegexThen<T> for StarMax<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        if self.0.may_capture() && continuation.mode() == Mode::Captures {
+            let iterations = LastIteration::new(captures);
+            let state = StarMaxState { repeat: &self.0, continuation, iterations, visited: None };
+            state.match_continue(text, position, captures)
+        } else {
+            let visited = Visited::default();
+            let visited = visited.in_mode(continuation.mode());
+            let state = StarMaxState { repeat: &self.0, continuation, iterations: (), visited };
+            state.match_continue(text, position, captures)
+        }
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set().optional()
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
+}
+
+impl<T,R> RegexRev<T> for StarMax<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        let visited = Visited::default();
+        let state = StarMaxState {
+            repeat: &self.0, continuation, iterations: (), visited: Some(&visited)
+        };
+        state.match_rev_continue(text, position)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlusMax<R>(pub R);
+
+impl<T,R> RegexThen<T> for PlusMax<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        if self.0.may_capture() && continuation.mode() == Mode::Captures {
+            let iterations = LastIteration::new(captures);
+            let state = StarMaxState { repeat: &self.0, continuation, iterations, visited: None };
+            self.0.match_then(text, position, captures, &state)
+        } else {
+            let visited = Visited::default();
+            let visited = visited.in_mode(continuation.mode());
+            let state = StarMaxState { repeat: &self.0, continuation, iterations: (), visited };
+            self.0.match_then(text, position, captures, &state)
+        }
+    }
+
+    fn literals(&self) -> Literals {
+        // Every match begins with (and contains) at least one `R`.
+        self.0.literals()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set()
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
+}
+
+impl<T,R> RegexRev<T> for PlusMax<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        let visited = Visited::default();
+        let state = StarMaxState {
+            repeat: &self.0, continuation, iterations: (), visited: Some(&visited)
+        };
+        self.0.match_rev_then(text, position, &state)
+    }
+}
+
+struct StarMaxState<'a,R:'a,C:'a,I> {
+    repeat: &'a R,
+    continuation: &'a C,
+    iterations: I,
+    /// Where the repetition has already been tried, if how it got
+    /// there cannot matter; see `Visited`.
+    visited: Option<&'a Visited>,
+}
+
+impl<'a,T,R,C,I> RegexContinuation<T> for StarMaxState<'a,R,C,I>
+    where T: ?Sized + Text, R: RegexThen<T>, C: RegexContinuation<T>, I: Iterations
+{
+    fn match_continue<'text>(&self,
+                             text: &'text T,
+                             start: usize,
+                             captures: &mut Captures<'text, T>)
+                             -> Option<usize>
+    {
+        // You may be wondering "where is the loop?" The answer is
+        // that the loop occurs by passing `self` as the
+        // *continuation* for `self.repeat`. This means that after we
+        // match the repeating part, we will resume in the same state,
+        // ready to try again, but with a different start point.  Only
+        // once we fail will we fallback to `self.continuation`.
+        if self.visited.is_some_and(|visited| visited.mark(start)) {
+            return None;
+        }
+        self.iterations.settle(captures, |iterations, captures| {
+            let state = StarMaxState { iterations, ..*self };
+            let captures_len = captures.len();
+            let result = match iterations.next(captures_len) {
+                None => state.repeat.match_then(text, start, captures, &state),
+                Some(iterations) => {
+                    let next = StarMaxState { iterations, ..*self };
+                    state.repeat.match_then(text, start, captures, &next)
+                }
+            };
+            match result {
+                Some(end) => Some(end),
+                None => {
+                    captures.truncate(captures_len);
+                    iterations.exit(state.continuation, text, start, captures)
+                }
+            }
+        })
+    }
+
+    fn mode(&self) -> Mode {
+        self.continuation.mode()
+    }
+}
+
+impl<'a,T,R,C,I> RegexRevContinuation<T> for StarMaxState<'a,R,C,I>
+    where T: ?Sized + Text, R: RegexRev<T>, C: RegexRevContinuation<T>
+{
+    fn match_rev_continue(&self,
+                          text: &T,
+                          start: usize)
+                          -> Option<usize>
+    {
+        if self.visited.is_some_and(|visited| visited.mark(start)) {
+            return None;
+        }
+        // As in the forward direction, the loop is formed by passing
+        // `self` as the continuation.
+        match self.repeat.match_rev_then(text, start, self) {
+            Some(end) => Some(end),
+            None => self.continuation.match_rev_continue(text, start),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct StarMin<R>(pub R);
+
+impl<T,R> RegexThen<T> for StarMin<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        if self.0.may_capture() && continuation.mode() == Mode::Captures {
+            let iterations = LastIteration::new(captures);
+            let state = StarMinState { repeat: &self.0, continuation, iterations, visited: None };
+            state.match_continue(text, position, captures)
+        } else {
+            let visited = Visited::default();
+            let visited = visited.in_mode(continuation.mode());
+            let state = StarMinState { repeat: &self.0, continuation, iterations: (), visited };
+            state.match_continue(text, position, captures)
+        }
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set().optional()
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
+}
+
+impl<T,R> RegexRev<T> for StarMin<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        let visited = Visited::default();
+        let state = StarMinState {
+            repeat: &self.0, continuation, iterations: (), visited: Some(&visited)
+        };
+        state.match_rev_continue(text, position)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlusMin<R>(pub R);
+
+impl<T,R> RegexThen<T> for PlusMin<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        if self.0.may_capture() && continuation.mode() == Mode::Captures {
+            let iterations = LastIteration::new(captures);
+            let state = StarMinState { repeat: &self.0, continuation, iterations, visited: None };
+            self.0.match_then(text, position, captures, &state)
+        } else {
+            let visited = Visited::default();
+            let visited = visited.in_mode(continuation.mode());
+            let state = StarMinState { repeat: &self.0, continuation, iterations: (), visited };
+            self.0.match_then(text, position, captures, &state)
+        }
+    }
+
+    fn literals(&self) -> Literals {
+        // Every match begins with (and contains) at least one `R`.
+        self.0.literals()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set()
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
+}
+
+impl<T,R> RegexRev<T> for PlusMin<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        let visited = Visited::default();
+        let state = StarMinState {
+            repeat: &self.0, continuation, iterations: (), visited: Some(&visited)
+        };
+        self.0.match_rev_then(text, position, &state)
+    }
+}
+
+struct StarMinState<'a,R:'a,C:'a,I> {
+    repeat: &'a R,
+    continuation: &'a C,
+    iterations: I,
+    /// Where the repetition has already been tried, if how it got
+    /// there cannot matter; see `Visited`.
+    visited: Option<&'a Visited>,
+}
+
+impl<'a,T,R,C,I> RegexContinuation<T> for StarMinState<'a,R,C,I>
+    where T: ?Sized + Text, R: RegexThen<T>, C: RegexContinuation<T>, I: Iterations
+{
+    fn match_continue<'text>(&self,
+                             text: &'text T,
+                             start: usize,
+                             captures: &mut Captures<'text, T>)
+                             -> Option<usize>
+    {
+        if self.visited.is_some_and(|visited| visited.mark(start)) {
+            return None;
+        }
+        self.iterations.settle(captures, |iterations, captures| {
+            let state = StarMinState { iterations, ..*self };
+            // First try what comes after us:
+            let captures_len = captures.len();
+            match iterations.exit(state.continuation, text, start, captures) {
+                Some(end) => Some(end),
+                None => {
+                    // If that fails, then try the repeat and come back to this point:
+                    captures.truncate(captures_len);
+                    match iterations.next(captures_len) {
+                        None => state.repeat.match_then(text, start, captures, &state),
+                        Some(iterations) => {
+                            let next = StarMinState { iterations, ..*self };
+                            state.repeat.match_then(text, start, captures, &next)
+                        }
+                    }
+                }
+            }
+        })
+    }
+
+    fn mode(&self) -> Mode {
+        self.continuation.mode()
+    }
+}
+
+impl<'a,T,R,C,I> RegexRevContinuation<T> for StarMinState<'a,R,C,I>
+    where T: ?Sized + Text, R: RegexRev<T>, C: RegexRevContinuation<T>
+{
+    fn match_rev_continue(&self,
+                          text: &T,
+                          start: usize)
+                          -> Option<usize>
+    {
+        if self.visited.is_some_and(|visited| visited.mark(start)) {
+            return None;
+        }
+        match self.continuation.match_rev_continue(text, start) {
+            Some(end) => Some(end),
+            None => self.repeat.match_rev_then(text, start, self),
+        }
+    }
+}
+
+/// Matches `R` at least `min` and at most `max` times (any number of
+/// times, if `None`), as many as possible, like `R{min,max}`. Unlike
+/// writing `R` out `max` times, each group inside is still one group,
+/// reporting only its last iteration.
+#[derive(Clone, Debug)]
+pub struct RepeatMax<R>(pub R, pub usize, pub Option<usize>);
+
+impl<T,R> RegexThen<T> for RepeatMax<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        let RepeatMax(ref repeat, min, max) = *self;
+        repeat_then(repeat, min, max, true, text, position, captures, continuation)
+    }
+
+    fn literals(&self) -> Literals {
+        repeat_literals::<T, R>(&self.0, self.1)
+    }
+
+    fn first_set(&self) -> FirstSet {
+        repeat_first_set::<T, R>(&self.0, self.1)
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
+}
+
+impl<T,R> RegexRev<T> for RepeatMax<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        let RepeatMax(ref repeat, min, max) = *self;
+        let state = RepeatState {
+            repeat, continuation, iterations: (), count: 0, min, max, greedy: true
+        };
+        state.match_rev_continue(text, position)
+    }
+}
+
+/// Like `RepeatMax`, but matching as few times as possible.
+#[derive(Clone, Debug)]
+pub struct RepeatMin<R>(pub R, pub usize, pub Option<usize>);
+
+impl<T,R> RegexThen<T> for RepeatMin<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        let RepeatMin(ref repeat, min, max) = *self;
+        repeat_then(repeat, min, max, false, text, position, captures, continuation)
+    }
+
+    fn literals(&self) -> Literals {
+        repeat_literals::<T, R>(&self.0, self.1)
+    }
+
+    fn first_set(&self) -> FirstSet {
+        repeat_first_set::<T, R>(&self.0, self.1)
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
+}
+
+impl<T,R> RegexRev<T> for RepeatMin<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        let RepeatMin(ref repeat, min, max) = *self;
+        let state = RepeatState {
+            repeat, continuation, iterations: (), count: 0, min, max, greedy: false
+        };
+        state.match_rev_continue(text, position)
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn repeat_then<'text,T,R,C>(repeat: &R,
+                            min: usize,
+                            max: Option<usize>,
+                            greedy: bool,
+                            text: &'text T,
+                            position: usize,
+                            captures: &mut Captures<'text, T>,
+                            continuation: &C)
+                            -> Option<usize>
+    where T: ?Sized + Text, R: RegexThen<T>, C: RegexContinuation<T>
+{
+    if repeat.may_capture() && continuation.mode() == Mode::Captures {
+        let iterations = LastIteration::new(captures);
+        let state = RepeatState { repeat, continuation, iterations, count: 0, min, max, greedy };
+        state.match_continue(text, position, captures)
+    } else {
+        let state = RepeatState {
+            repeat, continuation, iterations: (), count: 0, min, max, greedy
+        };
+        state.match_continue(text, position, captures)
+    }
+}
+
+fn repeat_literals<T: ?Sized + Text, R: RegexThen<T>>(repeat: &R, min: usize) -> Literals {
+    if min > 0 {
+        // Every match begins with (and contains) at least one `R`.
+        repeat.literals()
+    } else {
+        Literals::unknown()
+    }
+}
+
+fn repeat_first_set<T: ?Sized + Text, R: RegexThen<T>>(repeat: &R, min: usize) -> FirstSet {
+    if min > 0 {
+        repeat.first_set()
+    } else {
+        repeat.first_set().optional()
+    }
+}
+
+/// A counted repetition after `count` iterations. Since what it may do
+/// next depends on the count, not only the position, nothing is skipped
+/// as `Visited` would.
+struct RepeatState<'a,R:'a,C:'a,I> {
+    repeat: &'a R,
+    continuation: &'a C,
+    iterations: I,
+    count: usize,
+    min: usize,
+    max: Option<usize>,
+    greedy: bool,
+}
+
+impl<'a,R,C,I: Copy> RepeatState<'a,R,C,I> {
+    fn may_exit(&self) -> bool {
+        self.count >= self.min
+    }
+
+    fn may_repeat(&self) -> bool {
+        self.max.is_none_or(|max| self.count < max)
+    }
+
+    /// The state after another iteration, tracked by `iterations`.
+    fn another(&self, iterations: I) -> RepeatState<'a,R,C,I> {
+        RepeatState { iterations, count: self.count + 1, ..*self }
+    }
+}
+
+impl<'a,T,R,C,I> RegexContinuation<T> for RepeatState<'a,R,C,I>
+    where T: ?Sized + Text, R: RegexThen<T>, C: RegexContinuation<T>, I: Iterations
+{
+    fn match_continue<'text>(&self,
+                             text: &'text T,
+                             start: usize,
+                             captures: &mut Captures<'text, T>)
+                             -> Option<usize>
+    {
+        self.iterations.settle(captures, |iterations, captures| {
+            let captures_len = captures.len();
+            let repeat = |captures: &mut Captures<'text, T>| {
+                if !self.may_repeat() {
+                    return None;
+                }
+                let next = self.another(iterations.next(captures_len).unwrap_or(iterations));
+                self.repeat.match_then(text, start, captures, &next)
+            };
+            let exit = |captures: &mut Captures<'text, T>| {
+                if !self.may_exit() {
+                    return None;
+                }
+                iterations.exit(self.continuation, text, start, captures)
+            };
+            let result = if self.greedy { repeat(captures) } else { exit(captures) };
+            match result {
+                Some(end) => Some(end),
+                None => {
+                    captures.truncate(captures_len);
+                    if self.greedy { exit(captures) } else { repeat(captures) }
+                }
+            }
+        })
+    }
+
+    fn mode(&self) -> Mode {
+        self.continuation.mode()
+    }
+}
+
+impl<'a,T,R,C> RegexRevContinuation<T> for RepeatState<'a,R,C,()>
+    where T: ?Sized + Text, R: RegexRev<T>, C: RegexRevContinuation<T>
+{
+    fn match_rev_continue(&self,
+                          text: &T,
+                          start: usize)
+                          -> Option<usize>
+    {
+        let repeat = || {
+            if !self.may_repeat() {
+                return None;
+            }
+            self.repeat.match_rev_then(text, start, &self.another(()))
+        };
+        let exit = || {
+            if !self.may_exit() {
+                return None;
+            }
+            self.continuation.match_rev_continue(text, start)
+        };
+        if self.greedy {
+            repeat().or_else(exit)
+        } else {
+            exit().or_else(repeat)
+        }
+    }
+}
+
+/// The positions at which one entry into a repetition has already gone
+/// on to another iteration or to what follows. Where nothing but the
+/// position decides how that turns out, as when matching right to left
+/// or in `Mode::Ends`, neither of which looks at captures, trying a
+/// position again can only fail again, so it is skipped; nested
+/// repetitions then take polynomial rather than exponential time.
+/// Without `alloc`, nothing is remembered.
+#[derive(Default)]
+struct Visited {
+    #[cfg(feature = "alloc")]
+    bits: RefCell<Vec<u64>>,
+}
+
+impl Visited {
+    /// `Some(self)` in the modes where only the position matters.
+    fn in_mode(&self, mode: Mode) -> Option<&Visited> {
+        if mode == Mode::Ends { Some(self) } else { None }
+    }
+
+    /// Marks `position`, returning whether it already was.
+    #[cfg(feature = "alloc")]
+    fn mark(&self, position: usize) -> bool {
+        let mut bits = self.bits.borrow_mut();
+        let (word, bit) = (position / 64, 1 << (position % 64));
+        if bits.len() <= word {
+            bits.resize(word + 1, 0);
+        }
+        let marked = bits[word] & bit != 0;
+        bits[word] |= bit;
+        marked
+    }
+
+    #[cfg(not(feature = "alloc"))]
+    fn mark(&self, _position: usize) -> bool {
+        false
+    }
+}
+
+/// How a repetition keeps track of the captures of its iterations, so
+/// that each group inside reports only its last iteration: not at all
+/// (`()`), if nothing inside can capture, or with `LastIteration`.
+trait Iterations: Copy {
+    /// The tracking for another iteration, whose captures will begin at
+    /// `captures_len`, or `None` if the current tracking still holds.
+    fn next(&self, captures_len: usize) -> Option<Self>;
+
+    /// Leaves the repetition for `continuation`.
+    fn exit<'text,T,C>(&self,
+                       continuation: &C,
+                       text: &'text T,
+                       position: usize,
+                       captures: &mut Captures<'text, T>)
+                       -> Option<usize>
+        where T: ?Sized + Text, C: RegexContinuation<T>;
+
+    /// Runs `f`, where an iteration may have just ended, with the
+    /// tracking to go on with.
+    fn settle<'text,T,F>(&self, captures: &mut Captures<'text, T>, f: F) -> Option<usize>
+        where T: ?Sized + Text, F: FnOnce(Self, &mut Captures<'text, T>) -> Option<usize>
+    {
+        f(*self, captures)
+    }
+}
+
+impl Iterations for () {
+    fn next(&self, _captures_len: usize) -> Option<()> {
+        None
+    }
+
+    fn exit<'text,T,C>(&self,
+                       continuation: &C,
+                       text: &'text T,
+                       position: usize,
+                       captures: &mut Captures<'text, T>)
+                       -> Option<usize>
+        where T: ?Sized + Text, C: RegexContinuation<T>
+    {
+        continuation.match_continue(text, position, captures)
+    }
+}
+
+/// Tracks where the captures of the first and the last iteration begin,
+/// so that on leaving the repetition those of the others can be marked
+/// superseded: a `SUPERSEDED` capture spanning their indices is pushed
+/// after the rest, and if the match then fails, it is truncated away
+/// like any other capture. Only once the match succeeds does `Accept`
+/// drop the captures it spans, so trying to leave costs the same
+/// however many iterations came before.
+#[derive(Copy, Clone)]
+struct LastIteration {
+    /// Where the captures of the first iteration begin.
+    base: usize,
+    /// Where the captures of the last iteration begin.
+    last: usize,
+}
+
+/// The group of a capture standing for captures `start..end` (as
+/// indices into the captures, not the text) of earlier iterations.
+static SUPERSEDED: Group = Group { name: None };
+
+/// The group given by `drop_superseded` to the captures to drop.
+static DROPPED: Group = Group { name: None };
+
+fn is_in<T: ?Sized>(capture: &Capture<T>, group: &'static Group) -> bool {
+    capture.group.is_some_and(|g| ptr::eq(g, group))
+}
+
+/// Drops every `SUPERSEDED` capture from `captures`, with the captures
+/// it spans, where the first of `captures` is the `offset`th of the
+/// match. A `SUPERSEDED` capture only spans captures before it.
+fn drop_superseded<T: ?Sized>(captures: &mut Captures<T>, offset: usize) {
+    if !captures.iter().any(|capture| is_in(capture, &SUPERSEDED)) {
+        return;
+    }
+    for i in 0..captures.len() {
+        if is_in(&captures[i], &SUPERSEDED) {
+            let (start, end) = (captures[i].start - offset, captures[i].end - offset);
+            for capture in &mut captures[start..end] {
+                capture.group = Some(&DROPPED);
+            }
+        }
+    }
+    let mut kept = 0;
+    for i in 0..captures.len() {
+        let capture = captures[i];
+        if !is_in(&capture, &SUPERSEDED) && !is_in(&capture, &DROPPED) {
+            captures[kept] = capture;
+            kept += 1;
+        }
+    }
+    captures.truncate(kept);
+}
+
+impl LastIteration {
+    fn new<T: ?Sized>(captures: &[Capture<T>]) -> LastIteration {
+        LastIteration { base: captures.len(), last: captures.len() }
+    }
+
+    /// Runs `f` with the captures of every iteration but the last taken
+    /// out, putting them back if it fails.
+    #[cfg(not(feature = "alloc"))]
+    fn without_superseded<'text,T,F>(&self, captures: &mut Captures<'text, T>, f: F)
+                                     -> Option<usize>
+        where T: ?Sized + Text, F: FnOnce(&mut Captures<'text, T>) -> Option<usize>
+    {
+        if self.base == self.last {
+            return f(captures);
+        }
+        let superseded: Captures<'text, T> = captures.drain(self.base..self.last).collect();
+        let captures_len = captures.len();
+        let result = f(captures);
+        if result.is_none() {
+            captures.truncate(captures_len);
+            captures.splice(self.base..self.base, superseded);
+        }
+        result
+    }
+}
+
+impl Iterations for LastIteration {
+    fn next(&self, captures_len: usize) -> Option<LastIteration> {
+        if captures_len == self.last {
+            None
+        } else {
+            Some(LastIteration { last: captures_len, ..*self })
+        }
+    }
+
+    /// Marks the captures of every iteration but the last superseded,
+    /// for `continuation`.
+    fn exit<'text,T,C>(&self,
+                       continuation: &C,
+                       text: &'text T,
+                       position: usize,
+                       captures: &mut Captures<'text, T>)
+                       -> Option<usize>
+        where T: ?Sized + Text, C: RegexContinuation<T>
+    {
+        if self.base != self.last {
+            let superseded = Capture {
+                text, start: self.base, end: self.last, group: Some(&SUPERSEDED)
+            };
+            captures::record(captures, superseded)?;
+        }
+        continuation.match_continue(text, position, captures)
+    }
+
+    /// Without `alloc`, the captures of the previous iteration are
+    /// taken out as soon as another ends, rather than marked superseded
+    /// on leaving the repetition, so that however many times it repeats
+    /// they cannot fill a `CaptureArray`. Each iteration then keeps
+    /// those it took out on the stack, to put back if `f` fails.
+    #[cfg(not(feature = "alloc"))]
+    fn settle<'text,T,F>(&self, captures: &mut Captures<'text, T>, f: F) -> Option<usize>
+        where T: ?Sized + Text, F: FnOnce(Self, &mut Captures<'text, T>) -> Option<usize>
+    {
+        self.without_superseded(captures, |captures| {
+            f(LastIteration { last: self.base, ..*self }, captures)
+        })
+    }
+}
+
+/// Matches `R` or, failing that, nothing. A failed `R` may leave the
+/// captures of its groups behind, as any regex may; like `Alt`, this
+/// drops them before going on without it, so that a group that did not
+/// take part in the match is not reported.
+#[derive(Clone, Debug)]
+pub struct Question<R>(pub R);
+
+impl<T,R> RegexThen<T> for Question<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        let captures_len = captures.len();
+        match self.0.match_then(text, position, captures, continuation) {
+            Some(end) => Some(end),
+            None => {
+                captures.truncate(captures_len);
+                continuation.match_continue(text, position, captures)
+            }
+        }
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set().optional()
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
+}
+
+impl<T,R> RegexRev<T> for Question<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        match self.0.match_rev_then(text, position, continuation) {
+            Some(end) => Some(end),
+            None => continuation.match_rev_continue(text, position),
+        }
+    }
+}
+
+/// Like `Question`, but lazy: tries going on without `R` first.
+#[derive(Clone, Debug)]
+pub struct QuestionMin<R>(pub R);
+
+impl<T,R> RegexThen<T> for QuestionMin<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        let captures_len = captures.len();
+        match continuation.match_continue(text, position, captures) {
+            Some(end) => Some(end),
+            None => {
+                captures.truncate(captures_len);
+                self.0.match_then(text, position, captures, continuation)
+            }
+        }
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set().optional()
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
+}
+
+impl<T,R> RegexRev<T> for QuestionMin<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        match continuation.match_rev_continue(text, position) {
+            Some(end) => Some(end),
+            None => self.0.match_rev_then(text, position, continuation),
+        }
+    }
+}
+
+/// Matches `R` or, failing that, `U`.
+#[derive(Clone, Debug)]
+pub struct Alt<R,U>(pub R, pub U);
+
+impl<T,R,U> RegexThen<T> for Alt<R,U>
+    where T: ?Sized + Text, R: RegexThen<T>, U: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        let captures_len = captures.len();
+        match self.0.match_then(text, position, captures, continuation) {
+            Some(end) => Some(end),
+            None => {
+                captures.truncate(captures_len);
+                self.1.match_then(text, position, captures, continuation)
+            }
+        }
+    }
+
+    fn literals(&self) -> Literals {
+        let (left, right) = (self.0.literals(), self.1.literals());
+        if left == right {
+            left
+        } else {
+            Literals { zero_width: left.zero_width && right.zero_width, ..Literals::unknown() }
+        }
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set().union(self.1.first_set())
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture() || self.1.may_capture()
+    }
+}
+
+impl<T,R,U> RegexRev<T> for Alt<R,U>
+    where T: ?Sized + Text, R: RegexRev<T>, U: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        match self.0.match_rev_then(text, position, continuation) {
+            Some(end) => Some(end),
+            None => self.1.match_rev_then(text, position, continuation),
+        }
+    }
+}
+
+/// Matches `R` starting at `position` or at any later position,
+/// trying the earliest first. Equivalent to `(StarMin(Choice(YesChoice)),
+/// R)`, except that the literal and first-set analyses of `R` are used
+/// to jump over start positions where `R` cannot possibly match.
+///
+/// The analyses are redone on every search; `Prefiltered` and `Cached`
+/// do them once instead. Each start is tried forward in turn; the
+/// right-to-left pass of `reverse` is not used.
+#[derive(Clone, Debug)]
+pub struct Unanchored<R>(pub R);
+
+impl<T,R> RegexThen<T> for Unanchored<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        let prefilter = Prefilter::new::<T, R>(&self.0);
+        search(&prefilter, &self.0, text, position, captures, continuation)
+    }
+
+    fn literals(&self) -> Literals {
+        unanchored_literals(&self.0)
+    }
+}
+
+/// Like `Unanchored`, with a `Prefilter` computed beforehand for `R`.
+#[derive(Clone)]
+pub struct Prefiltered<'p,R>(pub &'p Prefilter, pub R);
+
+impl<'p,T,R> RegexThen<T> for Prefiltered<'p,R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        search(self.0, &self.1, text, position, captures, continuation)
+    }
+
+    fn literals(&self) -> Literals {
+        unanchored_literals(&self.1)
+    }
+}
+
+/// Like `Unanchored`, with the `Prefilter` for `R` kept in a `Cache`
+/// after the first search; how the functions `rusty_regex!` defines
+/// search.
+pub struct Cached<R>(pub &'static Cache, pub R);
+
+impl<T,R> RegexThen<T> for Cached<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        match self.0.get::<T, R>(&self.1) {
+            Some(prefilter) => search(prefilter, &self.1, text, position, captures, continuation),
+            None => {
+                let prefilter = Prefilter::new::<T, R>(&self.1);
+                search(&prefilter, &self.1, text, position, captures, continuation)
+            }
+        }
+    }
+
+    fn literals(&self) -> Literals {
+        unanchored_literals(&self.1)
+    }
+}
+
+fn unanchored_literals<T, R>(regex: &R) -> Literals
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    // Skipped text is part of the match, so only `required` survives.
+    Literals { required: regex.literals().required, ..Literals::unknown() }
+}
+
+/// Tries `regex` at each position from `position` on that `prefilter`
+/// does not rule out, earliest first.
+fn search<'text,T,R,C>(prefilter: &Prefilter,
+                       regex: &R,
+                       text: &'text T,
+                       position: usize,
+                       captures: &mut Captures<'text, T>,
+                       continuation: &C)
+                       -> Option<usize>
+    where T: ?Sized + Text, R: RegexThen<T>, C: RegexContinuation<T>
+{
+    let mut required_at = None;
+    let mut start = position;
+    loop {
+        start = prefilter.next_start(text.as_bytes(), start, &mut required_at)?;
+
+        let captures_len = captures.len();
+        if let Some(end) = regex.match_then(text, start, captures, continuation) {
+            return Some(end);
+        }
+        captures.truncate(captures_len);
+
+        start += text.char_at(start)?.1;
+    }
+}
+
+impl<T,R> RegexRev<T> for Unanchored<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        // Whatever precedes the match is skipped lazily, exactly as in
+        // `(StarMin(Choice(YesChoice)), R)`.
+        let skip = StarMin(Choice(YesChoice));
+        let state = SeqMidState { next: &skip, continuation };
+        self.0.match_rev_then(text, position, &state)
+    }
+}
+
+/// Matches `R` at `position` the POSIX way: of all the ways `R` can
+/// match there, the one that ends last, not the one found first. Ties
+/// are broken by the captures, compared group by group from left to
+/// right, preferring having one at all, then the longer one, then the
+/// earlier one; this follows the POSIX rule that each subexpression
+/// matches the longest string it can, given those before it.
+///
+/// Every way `R` can match is tried, which may take exponentially long
+/// for nested repetitions. Wrapped in `Unanchored`, this finds the
+/// leftmost-longest match.
+#[cfg(feature = "alloc")]
+#[derive(Clone, Debug)]
+pub struct Longest<R>(pub R);
+
+#[cfg(feature = "alloc")]
+impl<T,R> RegexThen<T> for Longest<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    fn match_then<'text,C>(&self,
+                           text: &'text T,
+                           position: usize,
+                           captures: &mut Captures<'text, T>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation<T>
+    {
+        let captures_len = captures.len();
+        let longest = LongestState { base: captures_len, best: RefCell::new(None) };
+        self.0.match_then(text, position, captures, &longest);
+        let (end, spans) = longest.best.into_inner()?;
+
+        captures.truncate(captures_len);
+        captures.extend(spans.into_iter().map(|(start, end, group)| {
+            Capture { text, start, end, group }
+        }));
+        continuation.match_continue(text, end, captures)
+    }
+
+    fn literals(&self) -> Literals {
+        self.0.literals()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set()
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T,R> RegexRev<T> for Longest<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        let longest = LongestRevState { best: Cell::new(None) };
+        self.0.match_rev_then(text, position, &longest);
+        continuation.match_rev_continue(text, longest.best.get()?)
+    }
+}
+
+// Where a match ends and the captures it added, without the text, so
+// that it can be kept past the call that found it.
+#[cfg(feature = "alloc")]
+type Span = (usize, usize, Option<&'static Group>);
+
+#[cfg(feature = "alloc")]
+struct LongestState {
+    base: usize,
+    best: RefCell<Option<(usize, Vec<Span>)>>,
+}
+
+#[cfg(feature = "alloc")]
+impl LongestState {
+    /// Whether a match ending at `end` with the captures `spans` beats
+    /// `best`. The captures of each are in the order their groups appear
+    /// in the pattern, with absent ones missing, so they are compared
+    /// group by group: where one has a group the other lacks, the one
+    /// with it wins. Groups without a `Group` are told apart by position
+    /// alone.
+    fn better(end: usize, spans: &[Span], best: &(usize, Vec<Span>)) -> bool {
+        if end != best.0 {
+            return end > best.0;
+        }
+        let same_group = |a: &Span, b: &Span| match (a.2, b.2) {
+            (Some(a), Some(b)) => a == b,
+            (a, b) => a.is_none() && b.is_none(),
+        };
+        let (mut spans, mut best) = (spans, &best.1[..]);
+        while let (Some(span), Some(best_span)) = (spans.first(), best.first()) {
+            if !same_group(span, best_span) {
+                // The one whose group comes first is missing from the
+                // other, if the other has the second group later on.
+                if best.iter().any(|best_span| same_group(span, best_span)) {
+                    return false;
+                }
+                if spans.iter().any(|span| same_group(span, best_span)) {
+                    return true;
+                }
+            }
+            let (&(start, end, _), &(best_start, best_end, _)) = (span, best_span);
+            if end - start != best_end - best_start {
+                return end - start > best_end - best_start;
+            }
+            if start != best_start {
+                return start < best_start;
+            }
+            spans = &spans[1..];
+            best = &best[1..];
+        }
+        !spans.is_empty()
+    }
 }
 
-impl RegexContinuation for Accept {
+#[cfg(feature = "alloc")]
+impl<T> RegexContinuation<T> for LongestState
+    where T: ?Sized + Text
+{
     fn match_continue<'text>(&self,
-                             _text: &'text str,
+                             _text: &'text T,
                              position: usize,
-                             _captures: &mut Vec<Capture<'text>>)
+                             captures: &mut Captures<'text, T>)
                              -> Option<usize>
     {
-        Some(position)
+        let mut kept = captures[self.base..].to_vec();
+        drop_superseded(&mut kept, self.base);
+        let spans: Vec<Span> = kept.iter()
+            .map(|capture| (capture.start, capture.end, capture.group))
+            .collect();
+        let mut best = self.best.borrow_mut();
+        if best.as_ref().is_none_or(|best| LongestState::better(position, &spans, best)) {
+            *best = Some((position, spans));
+        }
+        // Fail, to go on to the other ways of matching.
+        None
+    }
+}
+
+#[cfg(feature = "alloc")]
+struct LongestRevState {
+    best: Cell<Option<usize>>,
+}
+
+#[cfg(feature = "alloc")]
+impl<T> RegexRevContinuation<T> for LongestRevState
+    where T: ?Sized + Text
+{
+    fn match_rev_continue(&self, _text: &T, position: usize) -> Option<usize> {
+        if self.best.get().is_none_or(|best| position < best) {
+            self.best.set(Some(position));
+        }
+        None
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct End;
+pub struct CaptureRe<R>(pub R);
 
-impl RegexThen for End {
+impl<T,R> RegexThen<T> for CaptureRe<R>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
     fn match_then<'text,C>(&self,
-                           text: &'text str,
+                           text: &'text T,
                            position: usize,
-                           captures: &mut Vec<Capture<'text>>,
+                           captures: &mut Captures<'text, T>,
                            continuation: &C)
                            -> Option<usize>
-        where C: RegexContinuation
+        where C: RegexContinuation<T>
     {
-        if position == text.len() {
-            continuation.match_continue(text, position, captures)
-        } else {
-            None
-        }
+        capture_then(&self.0, None, text, position, captures, continuation)
+    }
+
+    fn literals(&self) -> Literals {
+        self.0.literals()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set()
+    }
+}
+
+impl<T,R> RegexRev<T> for CaptureRe<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        self.0.match_rev_then(text, position, continuation)
+    }
+}
+
+/// Where a group is written in a pattern, and its name, if it has one.
+/// `rusty_regex!` gives each group a `static Group`, so that the
+/// captures of one group's iterations can be told apart from those of
+/// other groups: two `Group`s are equal only if they are the same
+/// static.
+#[derive(Debug)]
+pub struct Group {
+    pub name: Option<&'static str>,
+}
+
+impl PartialEq for Group {
+    fn eq(&self, other: &Group) -> bool {
+        ptr::eq(self, other)
     }
 }
 
-impl<R,U> RegexThen for (R,U)
-    where R: RegexThen, U: RegexThen
+impl Eq for Group { }
+
+/// Like `CaptureRe`, but the capture carries its `Group`, so that it
+/// can be found by name (e.g., by `#[derive(FromCaptures)]`) and its
+/// iterations gathered by `history`.
+#[derive(Clone, Debug)]
+pub struct GroupRe<R>(pub &'static Group, pub R);
+
+impl<T,R> RegexThen<T> for GroupRe<R>
+    where T: ?Sized + Text, R: RegexThen<T>
 {
     fn match_then<'text,C>(&self,
-                           text: &'text str,
+                           text: &'text T,
                            position: usize,
-                           captures: &mut Vec<Capture<'text>>,
+                           captures: &mut Captures<'text, T>,
                            continuation: &C)
                            -> Option<usize>
-        where C: RegexContinuation
+        where C: RegexContinuation<T>
     {
-        let state = SeqMidState { next: &self.1, continuation: continuation };
-        self.0.match_then(text, position, captures, &state)
+        capture_then(&self.1, Some(self.0), text, position, captures, continuation)
+    }
+
+    fn literals(&self) -> Literals {
+        self.1.literals()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.1.first_set()
     }
 }
 
-struct SeqMidState<'r,R:'r,C:'r> {
-    next: &'r R,
-    continuation: &'r C,
+impl<T,R> RegexRev<T> for GroupRe<R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        self.1.match_rev_then(text, position, continuation)
+    }
+}
+
+/// Runs `regex` at the start of `text`, like a generated function, but
+/// keeping every iteration of each repeated group: one `Vec` per group,
+/// in the order the groups would otherwise be reported, holding its
+/// captures from first to last. Groups are told apart by their `Group`,
+/// so a `struct` used twice in a pattern reports its groups once.
+#[cfg(feature = "alloc")]
+pub fn history<'text,T,R>(regex: &R, text: &'text T) -> Option<Vec<Vec<Capture<'text, T>>>>
+    where T: ?Sized + Text, R: RegexThen<T>
+{
+    let mut captures = vec![];
+    regex.match_then(text, 0, &mut captures, &History)?;
+
+    let mut groups: Vec<Vec<Capture<'text, T>>> = vec![];
+    for capture in captures {
+        let earlier = capture.group.and_then(|group| {
+            groups.iter_mut().find(|earlier| earlier[0].group == Some(group))
+        });
+        match earlier {
+            Some(earlier) => earlier.push(capture),
+            None => groups.push(vec![capture]),
+        }
+    }
+    Some(groups)
 }
 
-impl<'r,R,C> RegexContinuation for SeqMidState<'r,R,C>
-    where R: RegexThen, C: RegexContinuation
+/// Accepts, telling repetitions to keep every iteration.
+#[cfg(feature = "alloc")]
+struct History;
+
+#[cfg(feature = "alloc")]
+impl<T> RegexContinuation<T> for History
+    where T: ?Sized + Text
 {
     fn match_continue<'text>(&self,
-                             text: &'text str,
+                             _text: &'text T,
                              position: usize,
-                             captures: &mut Vec<Capture<'text>>)
+                             _captures: &mut Captures<'text, T>)
                              -> Option<usize>
     {
-        self.next.match_then(text, position, captures, self.continuation)
+        Some(position)
+    }
+
+    fn mode(&self) -> Mode {
+        Mode::History
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct StarMax<R>(pub R);
+fn capture_then<'text,T,R,C>(regex: &R,
+                             group: Option<&'static Group>,
+                             text: &'text T,
+                             position: usize,
+                             captures: &mut Captures<'text, T>,
+                             continuation: &C)
+                             -> Option<usize>
+    where T: ?Sized + Text, R: RegexThen<T>, C: RegexContinuation<T>
+{
+    let index = captures.len();
+
+    let post_capture = PostCaptureRe {
+        index,
+        continuation,
+    };
+
+    captures::record(captures, Capture { text, start: position, end: position, group })?;
+
+    regex.match_then(text, position, captures, &post_capture)
+}
+
+struct PostCaptureRe<'r, C:'r> {
+    index: usize,
+    continuation: &'r C
+}
 
-impl<R> RegexThen for StarMax<R>
-    where R: RegexThen
+impl<'r, T, C> RegexContinuation<T> for PostCaptureRe<'r, C>
+    where T: ?Sized + Text, C: RegexContinuation<T>
 {
-    fn match_then<'text,C>(&self,
-                           text: &'text str,
-                           position: usize,
-                           captures: &mut Vec<Capture<'text>>,
-                           continuation: &C)
-                           -> Option<usize>
-        where C: RegexContinuation
+    fn match_continue<'text>(&self,
+                             text: &'text T,
+                             position: usize,
+                             captures: &mut Captures<'text, T>)
+                             -> Option<usize>
     {
-        let state = StarMaxState { repeat: &self.0, continuation: continuation };
-        state.match_continue(text, position, captures)
+        captures[self.index].end = position;
+        self.continuation.match_continue(text, position, captures)
+    }
+
+    fn mode(&self) -> Mode {
+        self.continuation.mode()
     }
 }
 
+/// Matches `R`, recording in the slot where it matched, for typed
+/// groups. If whatever follows fails, the slot's previous value is put
+/// back, so it always describes the match that succeeded.
 #[derive(Clone, Debug)]
-pub struct PlusMax<R>(pub R);
+pub struct Field<'s,R>(pub &'s Cell<Option<(usize, usize)>>, pub R);
 
-impl<R> RegexThen for PlusMax<R>
-    where R: RegexThen
+impl<'s,T,R> RegexThen<T> for Field<'s,R>
+    where T: ?Sized + Text, R: RegexThen<T>
 {
     fn match_then<'text,C>(&self,
-                           text: &'text str,
+                           text: &'text T,
                            position: usize,
-                           captures: &mut Vec<Capture<'text>>,
+                           captures: &mut Captures<'text, T>,
                            continuation: &C)
                            -> Option<usize>
-        where C: RegexContinuation
+        where C: RegexContinuation<T>
     {
-        let state = StarMaxState { repeat: &self.0, continuation: continuation };
-        self.0.match_then(text, position, captures, &state)
+        let state = FieldState { slot: self.0, start: position, continuation };
+        self.1.match_then(text, position, captures, &state)
+    }
+
+    fn literals(&self) -> Literals {
+        self.1.literals()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.1.first_set()
     }
 }
 
-struct StarMaxState<'a,R:'a,C:'a> {
-    repeat: &'a R,
-    continuation: &'a C,
+impl<'s,T,R> RegexRev<T> for Field<'s,R>
+    where T: ?Sized + Text, R: RegexRev<T>
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        self.1.match_rev_then(text, position, continuation)
+    }
+}
+
+struct FieldState<'r, C:'r> {
+    slot: &'r Cell<Option<(usize, usize)>>,
+    start: usize,
+    continuation: &'r C,
 }
 
-impl<'a,R,C> RegexContinuation for StarMaxState<'a,R,C>
-    where R: RegexThen, C: RegexContinuation
+impl<'r, T, C> RegexContinuation<T> for FieldState<'r, C>
+    where T: ?Sized + Text, C: RegexContinuation<T>
 {
     fn match_continue<'text>(&self,
-                             text: &'text str,
-                             start: usize,
-                             captures: &mut Vec<Capture<'text>>)
+                             text: &'text T,
+                             position: usize,
+                             captures: &mut Captures<'text, T>)
                              -> Option<usize>
     {
-        // You may be wondering "where is the loop?" The answer is
-        // that the loop occurs by passing `self` as the
-        // *continuation* for `self.repeat`. This means that after we
-        // match the repeating part, we will resume in the same state,
-        // ready to try again, but with a different start point.  Only
-        // once we fail will we fallback to `self.continuation`.
-        let captures_len = captures.len();
-        match self.repeat.match_then(text, start, captures, self) {
-            Some(end) => Some(end),
-            None => {
-                captures.truncate(captures_len);
-                self.continuation.match_continue(text, start, captures)
-            }
+        let previous = self.slot.replace(Some((self.start, position)));
+        let result = self.continuation.match_continue(text, position, captures);
+        if result.is_none() {
+            self.slot.set(previous);
         }
+        result
+    }
+
+    fn mode(&self) -> Mode {
+        self.continuation.mode()
     }
 }
 
+/// Matches `R`, but only where `F` accepts the text `R` matched;
+/// otherwise `R` backtracks to try a different match. Text that is not
+/// valid UTF-8 (possible in `bytes` mode) is always rejected.
 #[derive(Clone, Debug)]
-pub struct StarMin<R>(pub R);
+pub struct Check<R,F>(pub R, pub F);
 
-impl<R> RegexThen for StarMin<R>
-    where R: RegexThen
+/// Builds a `Check`, giving a closure passed as `predicate` its
+/// expected signature.
+pub fn check<R, F>(regex: R, predicate: F) -> Check<R, F>
+    where F: Fn(&str) -> bool
 {
-    fn match_then<'text,C>(&self,
-                           text: &'text str,
-                           position: usize,
-                           captures: &mut Vec<Capture<'text>>,
-                           continuation: &C)
-                           -> Option<usize>
-        where C: RegexContinuation
-    {
-        let state = StarMinState { repeat: &self.0, continuation: continuation };
-        state.match_continue(text, position, captures)
-    }
+    Check(regex, predicate)
 }
 
-#[derive(Clone, Debug)]
-pub struct PlusMin<R>(pub R);
+fn accepts<T, F>(text: &T, start: usize, end: usize, predicate: &F) -> bool
+    where T: ?Sized + Text, F: Fn(&str) -> bool
+{
+    let bytes = &text.as_bytes()[start..end];
+    ::core::str::from_utf8(bytes).is_ok_and(predicate)
+}
 
-impl<R> RegexThen for PlusMin<R>
-    where R: RegexThen
+impl<T,R,F> RegexThen<T> for Check<R,F>
+    where T: ?Sized + Text, R: RegexThen<T>, F: Fn(&str) -> bool
 {
     fn match_then<'text,C>(&self,
-                           text: &'text str,
+                           text: &'text T,
                            position: usize,
-                           captures: &mut Vec<Capture<'text>>,
+                           captures: &mut Captures<'text, T>,
                            continuation: &C)
                            -> Option<usize>
-        where C: RegexContinuation
+        where C: RegexContinuation<T>
     {
-        let state = StarMinState { repeat: &self.0, continuation: continuation };
+        let state = CheckState { start: position, predicate: &self.1, continuation };
         self.0.match_then(text, position, captures, &state)
     }
+
+    fn literals(&self) -> Literals {
+        self.0.literals()
+    }
+
+    fn first_set(&self) -> FirstSet {
+        self.0.first_set()
+    }
+
+    fn may_capture(&self) -> bool {
+        self.0.may_capture()
+    }
 }
 
-struct StarMinState<'a,R:'a,C:'a> {
-    repeat: &'a R,
-    continuation: &'a C,
+impl<T,R,F> RegexRev<T> for Check<R,F>
+    where T: ?Sized + Text, R: RegexRev<T>, F: Fn(&str) -> bool
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        // In this direction `start` is where `R` ends.
+        let state = CheckState { start: position, predicate: &self.1, continuation };
+        self.0.match_rev_then(text, position, &state)
+    }
+}
+
+struct CheckState<'r, F:'r, C:'r> {
+    start: usize,
+    predicate: &'r F,
+    continuation: &'r C,
 }
 
-impl<'a,R,C> RegexContinuation for StarMinState<'a,R,C>
-    where R: RegexThen, C: RegexContinuation
+impl<'r, T, F, C> RegexContinuation<T> for CheckState<'r, F, C>
+    where T: ?Sized + Text, F: Fn(&str) -> bool, C: RegexContinuation<T>
 {
     fn match_continue<'text>(&self,
-                             text: &'text str,
-                             start: usize,
-                             captures: &mut Vec<Capture<'text>>)
+                             text: &'text T,
+                             position: usize,
+                             captures: &mut Captures<'text, T>)
                              -> Option<usize>
     {
-        // First try what comes after us:
-        let captures_len = captures.len();
-        match self.continuation.match_continue(text, start, captures) {
-            Some(end) => Some(end),
-            None => {
-                // If that fails, then try the repeat and come back to this point:
-                captures.truncate(captures_len);
-                self.repeat.match_then(text, start, captures, self)
-            }
+        if accepts(text, self.start, position, self.predicate) {
+            self.continuation.match_continue(text, position, captures)
+        } else {
+            None
         }
     }
-}
 
-#[derive(Clone, Debug)]
-pub struct Question<R>(pub R);
+    fn mode(&self) -> Mode {
+        self.continuation.mode()
+    }
+}
 
-impl<R> RegexThen for Question<R>
-    where R: RegexThen
+impl<'r, T, F, C> RegexRevContinuation<T> for CheckState<'r, F, C>
+    where T: ?Sized + Text, F: Fn(&str) -> bool, C: RegexRevContinuation<T>
 {
-    fn match_then<'text,C>(&self,
-                           text: &'text str,
-                           position: usize,
-                           captures: &mut Vec<Capture<'text>>,
-                           continuation: &C)
-                           -> Option<usize>
-        where C: RegexContinuation
+    fn match_rev_continue(&self,
+                          text: &T,
+                          position: usize)
+                          -> Option<usize>
     {
-        match self.0.match_then(text, position, captures, continuation) {
-            Some(end) => Some(end),
-            None => continuation.match_continue(text, position, captures),
+        if accepts(text, position, self.start, self.predicate) {
+            self.continuation.match_rev_continue(text, position)
+        } else {
+            None
         }
     }
 }
 
 #[derive(Clone, Debug)]
-pub struct CaptureRe<R>(pub R);
+pub struct Literal(pub &'static str);
 
-impl<R> RegexThen for CaptureRe<R>
-    where R: RegexThen
+impl<T> RegexThen<T> for Literal
+    where T: ?Sized + Text
 {
     fn match_then<'text,C>(&self,
-                           text: &'text str,
+                           text: &'text T,
                            position: usize,
-                           captures: &mut Vec<Capture<'text>>,
+                           captures: &mut Captures<'text, T>,
                            continuation: &C)
                            -> Option<usize>
-        where C: RegexContinuation
+        where C: RegexContinuation<T>
     {
-        let index = captures.len();
-
-        let post_capture = PostCaptureRe {
-            index: index,
-            continuation: continuation,
-        };
+        if text.has_literal_at(position, self.0) {
+            let len = T::literal_len(self.0);
+            continuation.match_continue(text, position + len, captures)
+        } else {
+            None
+        }
+    }
 
-        captures.push(Capture { text: text, start: position, end: position });
+    fn literals(&self) -> Literals {
+        Literals::literal(self.0)
+    }
 
-        self.0.match_then(text, position, captures, &post_capture)
+    fn first_set(&self) -> FirstSet {
+        FirstSet::literal(self.0)
     }
-}
 
-struct PostCaptureRe<'r, C:'r> {
-    index: usize,
-    continuation: &'r C
+    fn may_capture(&self) -> bool {
+        false
+    }
 }
 
-impl<'r, C> RegexContinuation for PostCaptureRe<'r, C>
-    where C: RegexContinuation
+impl<T> RegexRev<T> for Literal
+    where T: ?Sized + Text
 {
-    fn match_continue<'text>(&self,
-                             text: &'text str,
-                             position: usize,
-                             captures: &mut Vec<Capture<'text>>)
-                             -> Option<usize>
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
     {
-        captures[self.index].end = position;
-        self.continuation.match_continue(text, position, captures)
+        if text.has_literal_before(position, self.0) {
+            let len = T::literal_len(self.0);
+            continuation.match_rev_continue(text, position - len)
+        } else {
+            None
+        }
     }
 }
 
+/// A byte string such as `b"\xFF"`, matched byte for byte. Only bytes
+/// can contain it, so it only matches `ByteText`.
 #[derive(Clone, Debug)]
-pub struct Literal(pub &'static str);
+pub struct ByteLiteral(pub &'static [u8]);
 
-impl RegexThen for Literal {
+impl<T> RegexThen<T> for ByteLiteral
+    where T: ?Sized + ByteText
+{
     fn match_then<'text,C>(&self,
-                           text: &'text str,
+                           text: &'text T,
                            position: usize,
-                           captures: &mut Vec<Capture<'text>>,
+                           captures: &mut Captures<'text, T>,
                            continuation: &C)
                            -> Option<usize>
-        where C: RegexContinuation
+        where C: RegexContinuation<T>
     {
-        if text[position..].starts_with(self.0) {
-            let len = self.0.len();
-            continuation.match_continue(text, position + len, captures)
+        if text.has_bytes_at(position, self.0) {
+            continuation.match_continue(text, position + self.0.len(), captures)
+        } else {
+            None
+        }
+    }
+
+    fn literals(&self) -> Literals {
+        if self.0.is_empty() { Literals::zero_width() } else { Literals::unknown() }
+    }
+
+    fn first_set(&self) -> FirstSet {
+        // Byte texts look up a first byte above `0x7F` as any non-ASCII
+        // character.
+        match self.0.first() {
+            Some(&b) => FirstSet::chars(&CharChoice(b as char)),
+            None => FirstSet::nullable(),
+        }
+    }
+
+    fn may_capture(&self) -> bool {
+        false
+    }
+}
+
+impl<T> RegexRev<T> for ByteLiteral
+    where T: ?Sized + ByteText
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        if text.has_bytes_before(position, self.0) {
+            continuation.match_rev_continue(text, position - self.0.len())
         } else {
             None
         }
     }
 }
 
+/// What a literal or a bare identifier in a `rusty_regex!` pattern
+/// stands for: literal text, a byte string, or a fragment defined with
+/// `rusty_regex! { fragment name = ... }`.
+#[diagnostic::on_unimplemented(
+    message = "`{Self}` cannot be used in a pattern",
+    label = "expected a `fragment`, a `&'static str` or a `&'static [u8]` constant")]
+pub trait IntoRegex {
+    type Regex;
+
+    fn into_regex(self) -> Self::Regex;
+}
+
+impl IntoRegex for &'static str {
+    type Regex = Literal;
+
+    fn into_regex(self) -> Literal {
+        Literal(self)
+    }
+}
+
+impl IntoRegex for &'static [u8] {
+    type Regex = ByteLiteral;
+
+    fn into_regex(self) -> ByteLiteral {
+        ByteLiteral(self)
+    }
+}
+
+impl<const N: usize> IntoRegex for &'static [u8; N] {
+    type Regex = ByteLiteral;
+
+    fn into_regex(self) -> ByteLiteral {
+        ByteLiteral(self)
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct Choice<CR:CharRange>(pub CR);
 
-impl<CR> RegexThen for Choice<CR>
-    where CR: CharRange
+impl<T,CR> RegexThen<T> for Choice<CR>
+    where T: ?Sized + Text, CR: CharRange
 {
     fn match_then<'text,C>(&self,
-                           text: &'text str,
+                           text: &'text T,
                            position: usize,
-                           captures: &mut Vec<Capture<'text>>,
+                           captures: &mut Captures<'text, T>,
                            continuation: &C)
                            -> Option<usize>
-        where C: RegexContinuation
+        where C: RegexContinuation<T>
     {
-        if let Some(c) = text[position..].chars().next() {
+        if let Some((c, l)) = text.char_at(position) {
             if self.0.test(c) {
-                let l = c.len_utf8();
                 return continuation.match_continue(text, position + l, captures);
             }
         }
 
         None
     }
+
+    fn first_set(&self) -> FirstSet {
+        FirstSet::chars(&self.0)
+    }
+
+    fn may_capture(&self) -> bool {
+        false
+    }
+}
+
+impl<T,CR> RegexRev<T> for Choice<CR>
+    where T: ?Sized + Text, CR: CharRange
+{
+    fn match_rev_then<C>(&self,
+                         text: &T,
+                         position: usize,
+                         continuation: &C)
+                         -> Option<usize>
+        where C: RegexRevContinuation<T>
+    {
+        if let Some((c, l)) = text.char_before(position) {
+            if self.0.test(c) {
+                return continuation.match_rev_continue(text, position - l);
+            }
+        }
+
+        None
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -335,6 +2123,10 @@ impl CharRange for CharChoice {
     fn test(&self, c: char) -> bool {
         self.0 == c
     }
+
+    fn may_match_non_ascii(&self) -> bool {
+        !self.0.is_ascii()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -347,6 +2139,10 @@ impl CharRange for RangeChoice {
         let c = c as u32;
         (lo <= c) && (c <= hi)
     }
+
+    fn may_match_non_ascii(&self) -> bool {
+        !self.1.is_ascii()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -365,6 +2161,10 @@ impl<CR1:CharRange,CR2:CharRange> CharRange for OrChoice<CR1,CR2> {
     fn test(&self, c: char) -> bool {
         self.0.test(c) || self.1.test(c)
     }
+
+    fn may_match_non_ascii(&self) -> bool {
+        self.0.may_match_non_ascii() || self.1.may_match_non_ascii()
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -374,6 +2174,10 @@ impl CharRange for NoChoice {
     fn test(&self, _: char) -> bool {
         false
     }
+
+    fn may_match_non_ascii(&self) -> bool {
+        false
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -401,7 +2205,11 @@ pub mod named_choices {
 
     impl CharRange for digit {
         fn test(&self, c: char) -> bool {
-            c >= '0' && c <= '9'
+            c.is_ascii_digit()
+        }
+
+        fn may_match_non_ascii(&self) -> bool {
+            false
         }
     }
 
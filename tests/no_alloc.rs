@@ -0,0 +1,146 @@
+//! Matching into a `CaptureBuf` kept from one match to the next, which
+//! allocates nothing once it has grown. Without `alloc`, captures go in
+//! a `CaptureArray` of `MAX_CAPTURES`; run with `cargo test
+//! --no-default-features` for the tests of that.
+
+#[macro_use]
+extern crate rusty_regex;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+
+#[cfg(not(feature = "alloc"))]
+use rusty_regex::captures::{CaptureArray, MAX_CAPTURES};
+#[cfg(not(feature = "alloc"))]
+use rusty_regex::util::{Accept, CaptureRe};
+#[cfg(not(feature = "alloc"))]
+use rusty_regex::{Capture, Captures, RegexContinuation, RegexThen};
+use rusty_regex::CaptureBuf;
+
+/// Counts the allocations made by each thread.
+struct Counting;
+
+thread_local! {
+    static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: Counting = Counting;
+
+/// How many allocations `f` makes.
+fn allocations<F: FnOnce()>(f: F) -> usize {
+    let before = ALLOCATIONS.with(Cell::get);
+    f();
+    ALLOCATIONS.with(Cell::get) - before
+}
+
+rusty_regex! {
+    buffered list_re = ^ ("," [:digit:]+)* END;
+    buffered many_re = ^ ("a")* END;
+    buffered backtrack_re = ^ ("a")* "ab" END;
+    buffered groups_re = ^ ("a") ("a") ("a") ("a") ("a") ("a") ("a") ("a")
+                           ("a") ("a") ("a") ("a") ("a") ("a") ("a") ("a") END;
+}
+
+#[test]
+fn warm_buffer_does_not_allocate() {
+    let text = ",1,22,333".repeat(10);
+    let mut captures = CaptureBuf::new();
+    assert!(list_re(&text, &mut captures));
+    let allocations = allocations(|| {
+        for _ in 0..100 {
+            assert!(list_re(&text, &mut captures));
+        }
+    });
+    assert_eq!(allocations, 0);
+    assert_eq!(captures.len(), 2);
+    assert_eq!(captures[1].to_str(), ",333");
+}
+
+#[test]
+#[cfg(not(feature = "alloc"))]
+fn repetition_keeps_only_last_iteration() {
+    let text = "a".repeat(4 * MAX_CAPTURES);
+    let mut captures = CaptureBuf::new();
+    assert!(many_re(&text, &mut captures));
+    assert_eq!(captures.len(), 2);
+    assert_eq!(captures[1], Capture::new(&text[..], text.len() - 1, text.len()));
+
+    // Backtracking into the repetition puts back the iteration before.
+    let text = "a".repeat(4 * MAX_CAPTURES) + "b";
+    assert!(backtrack_re(&text, &mut captures));
+    assert_eq!(captures.len(), 2);
+    assert_eq!(captures[1], Capture::new(&text[..], text.len() - 3, text.len() - 2));
+}
+
+/// `n` groups, each `CaptureRe` around the next, matching nothing, so
+/// that there can be as many as `MAX_CAPTURES` says.
+#[cfg(not(feature = "alloc"))]
+struct Nested(usize);
+
+/// A continuation behind a `dyn` reference, so that every level of
+/// `Nested` is matched with the same type of continuation, not one
+/// wrapped a level deeper.
+#[cfg(not(feature = "alloc"))]
+struct Erased<'c>(&'c dyn RegexContinuation);
+
+#[cfg(not(feature = "alloc"))]
+impl RegexContinuation for Erased<'_> {
+    fn match_continue<'text>(&self,
+                             text: &'text str,
+                             position: usize,
+                             captures: &mut Captures<'text>)
+                             -> Option<usize>
+    {
+        self.0.match_continue(text, position, captures)
+    }
+}
+
+#[cfg(not(feature = "alloc"))]
+impl RegexThen for Nested {
+    fn match_then<'text,C>(&self,
+                           text: &'text str,
+                           position: usize,
+                           captures: &mut Captures<'text>,
+                           continuation: &C)
+                           -> Option<usize>
+        where C: RegexContinuation
+    {
+        match self.0 {
+            0 => continuation.match_continue(text, position, captures),
+            n => CaptureRe(Nested(n - 1)).match_then(text, position, captures, &Erased(continuation)),
+        }
+    }
+}
+
+#[test]
+#[cfg(not(feature = "alloc"))]
+fn too_many_captures_fail_the_match() {
+    let mut captures = Captures::new();
+    assert!(Nested(MAX_CAPTURES).match_then("", 0, &mut captures, &Accept).is_some());
+    assert_eq!(captures.len(), MAX_CAPTURES);
+    let mut captures = Captures::new();
+    assert!(Nested(MAX_CAPTURES + 1).match_then("", 0, &mut captures, &Accept).is_none());
+
+    // The whole match and sixteen groups: one more than fits by default.
+    let text = "a".repeat(16);
+    let mut captures = CaptureBuf::new();
+    assert_eq!(groups_re(&text, &mut captures), MAX_CAPTURES > 16);
+    assert_eq!(captures.len(), if MAX_CAPTURES > 16 { 17 } else { 0 });
+
+    let mut array: CaptureArray<str, 1> = CaptureArray::new();
+    assert!(array.try_push(Capture::new("a", 0, 1)).is_ok());
+    assert!(array.try_push(Capture::new("a", 0, 1)).is_err());
+    assert_eq!(array.len(), 1);
+}